@@ -0,0 +1,188 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/chains.json");
+
+    #[cfg(feature = "static-registry")]
+    registry::generate();
+}
+
+/// Turns `src/chains.json` into `CHAINS` and its lookup tables, written to `$OUT_DIR/chains.rs`
+/// and included by `src/chains.rs`. The generated literals must match how `Chain` deserializes.
+#[cfg(feature = "static-registry")]
+mod registry {
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+    use std::fmt::Write;
+    use std::path::Path;
+
+    pub fn generate() {
+        let json = std::fs::read_to_string("src/chains.json").expect("could not read chains.json");
+        let chains: Vec<Value> = serde_json::from_str(&json).expect("could not parse chains.json");
+
+        // Each chain is a separate const, since older compilers need several gigabytes of memory
+        // to check a single array literal this large.
+        let mut out = String::new();
+        let mut by_id = BTreeMap::new();
+        let mut by_short_name = BTreeMap::new();
+        let mut by_name = BTreeMap::new();
+        let mut by_family = BTreeMap::<_, Vec<_>>::new();
+        for (index, chain) in chains.iter().enumerate() {
+            writeln!(
+                out,
+                "const CHAIN_{}: Chain = {};",
+                index,
+                chain_literal(chain)
+            )
+            .unwrap();
+
+            let chain_id = chain["chainId"].as_u64().expect("invalid chainId");
+            let short_name = string(&chain["shortName"]).to_lowercase();
+            let name = string(&chain["name"]).to_lowercase();
+            by_id.entry(chain_id).or_insert(index);
+            by_short_name.entry(short_name).or_insert(index);
+            by_name.entry(name).or_insert(index);
+            by_family
+                .entry(string(&chain["chain"]).to_lowercase())
+                .or_default()
+                .push(index);
+        }
+        let names: Vec<_> = (0..chains.len())
+            .map(|index| format!("CHAIN_{}", index))
+            .collect();
+        writeln!(
+            out,
+            "pub static CHAINS: &[Chain] = &[{}];",
+            names.join(", ")
+        )
+        .unwrap();
+
+        let mut map = phf_codegen::Map::new();
+        for (key, index) in &by_id {
+            map.entry(*key, &index.to_string());
+        }
+        writeln!(
+            out,
+            "static CHAINS_BY_ID: phf::Map<u64, usize> = {};",
+            map.build()
+        )
+        .unwrap();
+        for (table, keys) in [
+            ("CHAINS_BY_SHORT_NAME", &by_short_name),
+            ("CHAINS_BY_NAME", &by_name),
+        ] {
+            let mut map = phf_codegen::Map::new();
+            for (key, index) in keys {
+                map.entry(key.as_str(), &index.to_string());
+            }
+            writeln!(
+                out,
+                "static {}: phf::Map<&'static str, usize> = {};",
+                table,
+                map.build()
+            )
+            .unwrap();
+        }
+
+        let mut map = phf_codegen::Map::new();
+        for (key, indices) in &by_family {
+            map.entry(key.as_str(), &format!("&{:?}", indices));
+        }
+        writeln!(
+            out,
+            "static CHAINS_BY_FAMILY: phf::Map<&'static str, &'static [usize]> = {};",
+            map.build()
+        )
+        .unwrap();
+
+        let path = Path::new(&std::env::var("OUT_DIR").unwrap()).join("chains.rs");
+        std::fs::write(path, out).expect("could not write the chain registry");
+    }
+
+    fn chain_literal(chain: &Value) -> String {
+        let explorers = list(&chain["explorers"], |explorer| {
+            format!(
+                "Explorer {{ name: {}, url: {}, standard: {}, icon: {} }}",
+                str_literal(&explorer["name"]),
+                str_literal(&explorer["url"]),
+                str_literal(&explorer["standard"]),
+                option(&explorer["icon"], str_literal),
+            )
+        });
+        let features = list(&chain["features"], |feature| {
+            format!("Feature {{ name: {} }}", str_literal(&feature["name"]))
+        });
+        let status = option(&chain["status"], |status| {
+            let variant = match string(status) {
+                "active" => "Active",
+                "incubating" => "Incubating",
+                "deprecated" => "Deprecated",
+                _ => "Unknown",
+            };
+            format!("ChainStatus::{}", variant)
+        });
+        let parent = option(&chain["parent"], |parent| {
+            let bridges = list(&parent["bridges"], |bridge| {
+                format!("Bridge {{ url: {} }}", str_literal(&bridge["url"]))
+            });
+            format!(
+                "ParentChain {{ kind: {}, chain: {}, bridges: {} }}",
+                str_literal(&parent["type"]),
+                str_literal(&parent["chain"]),
+                bridges,
+            )
+        });
+        let currency = &chain["nativeCurrency"];
+
+        format!(
+            "Chain {{ name: {}, title: {}, short_name: {}, chain: {}, chain_id: {}, \
+             network_id: {}, rpc: {}, faucets: {}, native_currency: NativeCurrency {{ name: {}, \
+             symbol: {}, decimals: {} }}, info_url: {}, slip44: {}, ens: {}, explorers: {}, \
+             features: {}, status: {}, parent: {}, red_flags: {} }}",
+            str_literal(&chain["name"]),
+            option(&chain["title"], str_literal),
+            str_literal(&chain["shortName"]),
+            str_literal(&chain["chain"]),
+            chain["chainId"],
+            chain["networkId"],
+            list(&chain["rpc"], str_literal),
+            list(&chain["faucets"], str_literal),
+            str_literal(&currency["name"]),
+            str_literal(&currency["symbol"]),
+            currency["decimals"],
+            str_literal(&chain["infoURL"]),
+            option(&chain["slip44"], Value::to_string),
+            option(&chain["ens"], |ens| {
+                format!("EnsInfo {{ registry: {} }}", str_literal(&ens["registry"]))
+            }),
+            explorers,
+            features,
+            status,
+            parent,
+            list(&chain["redFlags"], str_literal),
+        )
+    }
+
+    fn string(value: &Value) -> &str {
+        value.as_str().expect("expected a string")
+    }
+
+    fn str_literal(value: &Value) -> String {
+        format!("Cow::Borrowed({:?})", string(value))
+    }
+
+    fn option(value: &Value, literal: impl Fn(&Value) -> String) -> String {
+        match value {
+            Value::Null => "None".to_string(),
+            value => format!("Some({})", literal(value)),
+        }
+    }
+
+    /// A borrowed slice literal, for a list that defaults to empty when missing.
+    fn list(value: &Value, literal: impl Fn(&Value) -> String) -> String {
+        let items: Vec<_> = value
+            .as_array()
+            .map(|items| items.iter().map(literal).collect())
+            .unwrap_or_default();
+        format!("Cow::Borrowed(&[{}])", items.join(", "))
+    }
+}
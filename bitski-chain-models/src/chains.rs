@@ -1,39 +1,378 @@
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
 use serde::Deserialize;
 
-#[derive(Clone, Debug, Deserialize)]
+/// An entry in the chain list from <https://chainid.network>.
+///
+/// Strings and lists are borrowed for the chains generated by the `static-registry` feature, and
+/// owned for chains parsed at runtime.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Chain {
-    pub name: String,
-    pub short_name: String,
-    pub chain: String,
+    pub name: Cow<'static, str>,
+    /// A longer name, for chains whose `name` is ambiguous, such as testnets.
+    pub title: Option<Cow<'static, str>>,
+    pub short_name: Cow<'static, str>,
+    pub chain: Cow<'static, str>,
     pub chain_id: u64,
+    pub network_id: u64,
     #[serde(default)]
-    pub rpc: Vec<String>,
+    pub rpc: Cow<'static, [Cow<'static, str>]>,
+    #[serde(default)]
+    pub faucets: Cow<'static, [Cow<'static, str>]>,
+    pub native_currency: NativeCurrency,
+    #[serde(rename = "infoURL")]
+    pub info_url: Cow<'static, str>,
+    /// The coin type registered in SLIP-0044, if any.
+    pub slip44: Option<u64>,
+    pub ens: Option<EnsInfo>,
+    #[serde(default)]
+    pub explorers: Cow<'static, [Explorer]>,
+    #[serde(default)]
+    pub features: Cow<'static, [Feature]>,
+    pub status: Option<ChainStatus>,
+    /// The chain this chain settles to, for L2s and shards.
+    pub parent: Option<ParentChain>,
+    /// Warnings about the chain, such as `reusedChainId`.
+    #[serde(default)]
+    pub red_flags: Cow<'static, [Cow<'static, str>]>,
+}
+
+impl Chain {
+    /// Returns whether the chain supports a feature, such as `EIP1559`.
+    pub fn has_feature(&self, name: &str) -> bool {
+        self.features.iter().any(|feature| feature.name == name)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct NativeCurrency {
+    pub name: Cow<'static, str>,
+    pub symbol: Cow<'static, str>,
+    pub decimals: u8,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Explorer {
+    pub name: Cow<'static, str>,
+    pub url: Cow<'static, str>,
+    /// The URL scheme the explorer follows, `EIP3091` or `none`.
+    pub standard: Cow<'static, str>,
+    pub icon: Option<Cow<'static, str>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct EnsInfo {
+    pub registry: Cow<'static, str>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Feature {
+    pub name: Cow<'static, str>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainStatus {
+    Active,
+    Incubating,
+    Deprecated,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct ParentChain {
+    /// The kind of child chain, such as `L2` or `shard`.
+    #[serde(rename = "type")]
+    pub kind: Cow<'static, str>,
+    /// The parent chain, in the form `eip155-<chain id>`.
+    pub chain: Cow<'static, str>,
+    #[serde(default)]
+    pub bridges: Cow<'static, [Bridge]>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+pub struct Bridge {
+    pub url: Cow<'static, str>,
 }
 
+#[cfg(not(any(feature = "static-registry", feature = "runtime-registry")))]
+compile_error!("either the `runtime-registry` or the `static-registry` feature must be enabled");
+
+#[cfg(not(feature = "static-registry"))]
 const CHAINS_JSON: &str = include_str!("chains.json");
 
-pub fn chains() -> Result<Vec<Chain>, anyhow::Error> {
-    let chains = serde_json::from_str(CHAINS_JSON)?;
-    Ok(chains)
+// Generated by build.rs from chains.json: `CHAINS`, and perfect-hash tables from each lookup key
+// to an index in `CHAINS`.
+#[cfg(feature = "static-registry")]
+include!(concat!(env!("OUT_DIR"), "/chains.rs"));
+
+#[cfg(not(feature = "static-registry"))]
+static REGISTRY: once_cell::sync::Lazy<ChainRegistry> = once_cell::sync::Lazy::new(|| {
+    ChainRegistry::new(serde_json::from_str(CHAINS_JSON).expect("could not parse chains.json"))
+});
+
+#[cfg(feature = "static-registry")]
+static REGISTRY: ChainRegistry = ChainRegistry {
+    chains: Cow::Borrowed(CHAINS),
+    index: Index::Generated,
+};
+
+/// Maps lookup keys to positions in a chain list.
+#[derive(Debug)]
+enum Index {
+    /// Built by [ChainRegistry::new]. Ordered maps, so the index also builds without `std`.
+    Built {
+        by_id: BTreeMap<u64, usize>,
+        by_short_name: BTreeMap<String, usize>,
+        by_name: BTreeMap<String, usize>,
+        /// Every chain with each `chain` code, in list order.
+        by_family: BTreeMap<String, Vec<usize>>,
+    },
+    /// The tables generated with `CHAINS`.
+    #[cfg(feature = "static-registry")]
+    Generated,
+}
+
+impl Index {
+    fn by_id(&self, chain_id: u64) -> Option<usize> {
+        match self {
+            Index::Built { by_id, .. } => by_id.get(&chain_id).copied(),
+            #[cfg(feature = "static-registry")]
+            Index::Generated => CHAINS_BY_ID.get(&chain_id).copied(),
+        }
+    }
+
+    fn by_short_name(&self, short_name: &str) -> Option<usize> {
+        match self {
+            Index::Built { by_short_name, .. } => by_short_name.get(short_name).copied(),
+            #[cfg(feature = "static-registry")]
+            Index::Generated => CHAINS_BY_SHORT_NAME.get(short_name).copied(),
+        }
+    }
+
+    fn by_name(&self, name: &str) -> Option<usize> {
+        match self {
+            Index::Built { by_name, .. } => by_name.get(name).copied(),
+            #[cfg(feature = "static-registry")]
+            Index::Generated => CHAINS_BY_NAME.get(name).copied(),
+        }
+    }
+
+    fn by_family(&self, family: &str) -> &[usize] {
+        match self {
+            Index::Built { by_family, .. } => by_family.get(family).map_or(&[], Vec::as_slice),
+            #[cfg(feature = "static-registry")]
+            Index::Generated => CHAINS_BY_FAMILY.get(family).copied().unwrap_or(&[]),
+        }
+    }
 }
 
-pub fn chain_from_str(value: &str) -> Result<Chain, anyhow::Error> {
-    let chains = chains()?;
+/// The chain list, indexed by chain id, short name, name and `chain` code.
+#[derive(Debug)]
+pub struct ChainRegistry {
+    chains: Cow<'static, [Chain]>,
+    index: Index,
+}
 
-    let chain = chains
+impl ChainRegistry {
+    pub fn new(chains: Vec<Chain>) -> Self {
+        let mut by_id = BTreeMap::new();
+        let mut by_short_name = BTreeMap::new();
+        let mut by_name = BTreeMap::new();
+        let mut by_family = BTreeMap::<_, Vec<_>>::new();
+        for (index, chain) in chains.iter().enumerate() {
+            by_id.entry(chain.chain_id).or_insert(index);
+            by_short_name
+                .entry(chain.short_name.to_lowercase())
+                .or_insert(index);
+            by_name.entry(chain.name.to_lowercase()).or_insert(index);
+            by_family
+                .entry(chain.chain.to_lowercase())
+                .or_default()
+                .push(index);
+        }
+        ChainRegistry {
+            chains: Cow::Owned(chains),
+            index: Index::Built {
+                by_id,
+                by_short_name,
+                by_name,
+                by_family,
+            },
+        }
+    }
+
+    pub fn chains(&self) -> &[Chain] {
+        &self.chains
+    }
+
+    pub fn by_id(&self, chain_id: u64) -> Option<&Chain> {
+        self.index.by_id(chain_id).map(|index| &self.chains[index])
+    }
+
+    /// Looks up a chain by short name, ignoring case.
+    pub fn by_short_name(&self, short_name: &str) -> Option<&Chain> {
+        self.index
+            .by_short_name(&short_name.to_lowercase())
+            .map(|index| &self.chains[index])
+    }
+
+    /// Looks up a chain by name, ignoring case.
+    pub fn by_name(&self, name: &str) -> Option<&Chain> {
+        self.index
+            .by_name(&name.to_lowercase())
+            .map(|index| &self.chains[index])
+    }
+
+    /// Returns every chain matching `value`, ignoring case, each with the field it matched.
+    ///
+    /// Matches are ordered by field: chain id, then short name, then name, then `chain` code,
+    /// which is shared by a network's mainnet and testnets. Chains matching the same field are in
+    /// list order, and a chain matching several fields is only returned for the first.
+    pub fn lookup(&self, value: &str) -> Vec<ChainMatch<'_>> {
+        let key = value.to_lowercase();
+        let id_match = value.parse().ok().and_then(|id| self.index.by_id(id));
+        let candidates = [
+            (MatchField::ChainId, id_match),
+            (MatchField::ShortName, self.index.by_short_name(&key)),
+            (MatchField::Name, self.index.by_name(&key)),
+        ]
         .into_iter()
-        .find(|chain| {
-            [
-                chain.name.to_lowercase(),
-                chain.chain.to_lowercase(),
-                chain.short_name.to_lowercase(),
-                chain.chain_id.to_string(),
-            ]
-            .contains(&value.to_lowercase())
+        .filter_map(|(field, index)| Some((field, index?)))
+        .chain(
+            self.index
+                .by_family(&key)
+                .iter()
+                .map(|&index| (MatchField::Family, index)),
+        );
+
+        let mut matches: Vec<ChainMatch<'_>> = Vec::new();
+        for (field, index) in candidates {
+            let chain = &self.chains[index];
+            if !matches
+                .iter()
+                .any(|found| core::ptr::eq(found.chain, chain))
+            {
+                matches.push(ChainMatch { chain, field });
+            }
+        }
+        matches
+    }
+
+    /// Returns the best match for `value`, in the order of [ChainRegistry::lookup].
+    pub fn resolve(&self, value: &str) -> Result<&Chain, LookupError> {
+        self.lookup(value)
+            .first()
+            .map(|found| found.chain)
+            .ok_or_else(|| LookupError::NotFound(value.to_string()))
+    }
+
+    /// Returns the chain matching `value`, or an error if it matches more than one chain by any
+    /// field.
+    pub fn resolve_strict(&self, value: &str) -> Result<&Chain, LookupError> {
+        match self.lookup(value).as_slice() {
+            [] => Err(LookupError::NotFound(value.to_string())),
+            [found] => Ok(found.chain),
+            matches => Err(LookupError::Ambiguous {
+                value: value.to_string(),
+                candidates: matches
+                    .iter()
+                    .map(|found| (found.chain.chain_id, found.field))
+                    .collect(),
+            }),
+        }
+    }
+}
+
+/// The field of a [Chain] that a lookup matched, from highest to lowest priority.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MatchField {
+    ChainId,
+    ShortName,
+    Name,
+    /// The `chain` code, such as `ETH`.
+    Family,
+}
+
+impl fmt::Display for MatchField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MatchField::ChainId => "chain id",
+            MatchField::ShortName => "short name",
+            MatchField::Name => "name",
+            MatchField::Family => "chain code",
         })
-        .ok_or_else(|| anyhow::anyhow!("Chain not found"))?;
-    Ok(chain)
+    }
+}
+
+/// A chain returned by [ChainRegistry::lookup].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainMatch<'a> {
+    pub chain: &'a Chain,
+    pub field: MatchField,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LookupError {
+    NotFound(String),
+    /// The value matched several chains, listed by chain id with the field each matched.
+    Ambiguous {
+        value: String,
+        candidates: Vec<(u64, MatchField)>,
+    },
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::NotFound(value) => write!(f, "Chain not found: {}", value),
+            LookupError::Ambiguous { value, candidates } => {
+                write!(f, "{} matches {} chains:", value, candidates.len())?;
+                for (chain_id, field) in candidates {
+                    write!(f, " {} by {},", chain_id, field)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LookupError {}
+
+/// Returns the registry of the embedded chain list. Without the `static-registry` feature, the
+/// list is parsed on first use.
+pub fn registry() -> &'static ChainRegistry {
+    &REGISTRY
+}
+
+pub fn chains() -> &'static [Chain] {
+    registry().chains()
+}
+
+/// Returns the chain matching `value` by id, short name, name or `chain` code, preferring them in
+/// that order. See [ChainRegistry::lookup].
+pub fn chain_from_str(value: &str) -> Result<&'static Chain, anyhow::Error> {
+    registry().resolve(value).map_err(lookup_error)
+}
+
+/// Like [chain_from_str], but fails if `value` matches more than one chain.
+pub fn chain_from_str_strict(value: &str) -> Result<&'static Chain, anyhow::Error> {
+    registry().resolve_strict(value).map_err(lookup_error)
+}
+
+/// Wraps a [LookupError] so that it can be recovered with `downcast_ref`, including without `std`,
+/// where it doesn't implement `std::error::Error`.
+fn lookup_error(error: LookupError) -> anyhow::Error {
+    #[cfg(feature = "std")]
+    return error.into();
+    #[cfg(not(feature = "std"))]
+    return anyhow::Error::msg(error);
 }
 
 #[cfg(test)]
@@ -53,4 +392,87 @@ mod tests {
         chain_from_str("1").expect("could not get chain 1");
         chain_from_str("137").expect("could not get chain 137");
     }
+
+    #[test]
+    fn test_registry_lookups() {
+        let registry = super::registry();
+        assert_eq!(registry.by_id(137).unwrap().short_name, "matic");
+        assert_eq!(registry.by_short_name("GOR").unwrap().chain_id, 5);
+        assert_eq!(registry.by_name("ethereum mainnet").unwrap().chain_id, 1);
+        assert!(registry.by_id(u64::MAX).is_none());
+
+        // lookups borrow from the same registry
+        let first = super::chain_from_str("eth").unwrap();
+        let second = super::chain_from_str("ETH").unwrap();
+        assert!(core::ptr::eq(first, second));
+    }
+
+    #[cfg(feature = "static-registry")]
+    #[test]
+    fn test_generated_chains() {
+        use super::{Chain, ChainRegistry};
+        use alloc::vec::Vec;
+        let parsed: Vec<Chain> = serde_json::from_str(include_str!("chains.json")).unwrap();
+        assert_eq!(super::CHAINS, parsed.as_slice());
+
+        let runtime = ChainRegistry::new(parsed);
+        for value in ["eth", "ETH", "137", "gor", "Polygon Mainnet", "ubq"] {
+            assert_eq!(super::registry().lookup(value), runtime.lookup(value));
+        }
+    }
+
+    #[test]
+    fn test_ambiguous_lookups() {
+        use super::{LookupError, MatchField};
+        use alloc::string::ToString;
+        let registry = super::registry();
+
+        // the short name is preferred over the chain code shared with the testnets
+        let matches = registry.lookup("eth");
+        assert_eq!(matches[0].chain.chain_id, 1);
+        assert_eq!(matches[0].field, MatchField::ShortName);
+        assert!(matches.len() > 1);
+        assert!(matches[1..]
+            .iter()
+            .all(|found| found.field == MatchField::Family && found.chain.chain == "ETH"));
+        assert_eq!(super::chain_from_str("eth").unwrap().chain_id, 1);
+
+        let error = registry.resolve_strict("eth").unwrap_err();
+        assert!(matches!(
+            error,
+            LookupError::Ambiguous { ref candidates, .. }
+                if candidates[0] == (1, MatchField::ShortName)
+        ));
+        assert_eq!(registry.resolve_strict("137").unwrap().short_name, "matic");
+        assert_eq!(
+            registry.resolve_strict("nope"),
+            Err(LookupError::NotFound("nope".to_string()))
+        );
+        let error = super::chain_from_str_strict("eth").unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<LookupError>(),
+            Some(LookupError::Ambiguous { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chain_metadata() {
+        use super::chain_from_str;
+        let eth = chain_from_str("eth").expect("could not get eth chain");
+        assert_eq!(eth.native_currency.symbol, "ETH");
+        assert_eq!(eth.native_currency.decimals, 18);
+        assert_eq!(eth.explorers[0].url, "https://etherscan.io");
+        assert_eq!(eth.slip44, Some(60));
+        assert!(eth.ens.is_some());
+        assert!(eth.has_feature("EIP1559"));
+
+        let arbitrum = chain_from_str("arb1").expect("could not get arbitrum chain");
+        let parent = arbitrum
+            .parent
+            .as_ref()
+            .expect("arbitrum has no parent chain");
+        assert_eq!(parent.kind, "L2");
+        assert_eq!(parent.chain, "eip155-1");
+        assert_eq!(parent.bridges[0].url, "https://bridge.arbitrum.io");
+    }
 }
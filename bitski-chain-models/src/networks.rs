@@ -1,9 +1,83 @@
 use crate::chains;
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
 
+/// The kind of server behind an RPC URL, which decides how providers talk to it.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum EndpointKind {
+    /// The Bitski API, which takes an API key and serves cacheable reads on its REST route.
+    Bitski,
+    /// A generic JSON-RPC node, such as a local dev node. Every call is sent as JSON-RPC, and the
+    /// API key is never sent.
+    JsonRpc,
+}
+
+impl EndpointKind {
+    /// Guesses the kind of server from an RPC URL: [EndpointKind::Bitski] for `bitski.com` hosts,
+    /// and [EndpointKind::JsonRpc] otherwise.
+    pub fn from_url(rpc_url: &str) -> Self {
+        let authority = rpc_url
+            .split_once("://")
+            .map_or(rpc_url, |(_scheme, rest)| rest)
+            .split(['/', '?', '#'])
+            .next()
+            .unwrap_or_default();
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_userinfo, host)| host)
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match host == "bitski.com" || host.ends_with(".bitski.com") {
+            true => EndpointKind::Bitski,
+            false => EndpointKind::JsonRpc,
+        }
+    }
+}
+
+/// An RPC endpoint for a chain.
+///
+/// Build one with [Network::new], or from a chain name or id with `Network::try_from`. New fields
+/// may be added in minor releases, so it can't be built with a struct literal outside this crate.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct Network {
     pub rpc_url: String,
     pub chain_id: u64,
+    pub endpoint_kind: EndpointKind,
+}
+
+impl Network {
+    /// A network for `rpc_url`, with the [EndpointKind] guessed from the URL by
+    /// [EndpointKind::from_url].
+    pub fn new(rpc_url: impl Into<String>, chain_id: u64) -> Self {
+        let rpc_url = rpc_url.into();
+        Network {
+            endpoint_kind: EndpointKind::from_url(&rpc_url),
+            rpc_url,
+            chain_id,
+        }
+    }
+
+    /// Sets the kind of server behind `rpc_url`, such as [EndpointKind::Bitski] for a Bitski
+    /// staging or test server.
+    pub fn with_endpoint_kind(mut self, endpoint_kind: EndpointKind) -> Self {
+        self.endpoint_kind = endpoint_kind;
+        self
+    }
+
+    /// The WebSocket endpoint for this network, derived from `rpc_url`.
+    pub fn ws_url(&self) -> String {
+        if let Some(rest) = self.rpc_url.strip_prefix("https://") {
+            format!("wss://{}", rest)
+        } else if let Some(rest) = self.rpc_url.strip_prefix("http://") {
+            format!("ws://{}", rest)
+        } else {
+            self.rpc_url.clone()
+        }
+    }
 }
 
 impl TryFrom<&str> for Network {
@@ -15,6 +89,7 @@ impl TryFrom<&str> for Network {
                 return Ok(Network {
                     rpc_url: "https://api.bitski.com/v1/web3/mainnet".to_owned(),
                     chain_id: 1,
+                    endpoint_kind: EndpointKind::Bitski,
                 })
             }
             // remap to the short name since 'goerli' isn't in the list
@@ -25,6 +100,7 @@ impl TryFrom<&str> for Network {
         Ok(Network {
             rpc_url: format!("https://api.bitski.com/v1/web3/{}", chain.chain_id),
             chain_id: chain.chain_id,
+            endpoint_kind: EndpointKind::Bitski,
         })
     }
 }
@@ -39,4 +115,47 @@ fn test_chain_name_try_from() {
 
     let n = Network::try_from("polygon").expect("could not get polygon chain");
     assert_eq!(n.chain_id, 137);
+    assert_eq!(n.endpoint_kind, EndpointKind::Bitski);
+}
+
+#[test]
+fn test_ws_url() {
+    let n = Network::try_from("polygon").expect("could not get polygon chain");
+    assert_eq!(n.ws_url(), "wss://api.bitski.com/v1/web3/137");
+
+    let n = Network {
+        rpc_url: "http://localhost:8545".to_owned(),
+        chain_id: 1337,
+        endpoint_kind: EndpointKind::JsonRpc,
+    };
+    assert_eq!(n.ws_url(), "ws://localhost:8545");
+}
+
+#[test]
+fn test_endpoint_kind_from_url() {
+    for url in [
+        "https://api.bitski.com/v1/web3/mainnet",
+        "https://API.Bitski.com:443/v1/web3/1",
+        "wss://bitski.com/v1/web3/1",
+    ] {
+        assert_eq!(EndpointKind::from_url(url), EndpointKind::Bitski, "{}", url);
+    }
+    for url in [
+        "http://localhost:8545",
+        "https://bitski.com.example.org/v1/web3/1",
+        "https://example.org/?next=api.bitski.com",
+        "https://api.bitski.com@example.org/",
+    ] {
+        assert_eq!(
+            EndpointKind::from_url(url),
+            EndpointKind::JsonRpc,
+            "{}",
+            url
+        );
+    }
+
+    let network = Network::new("http://localhost:8545", 1337);
+    assert_eq!(network.endpoint_kind, EndpointKind::JsonRpc);
+    let network = network.with_endpoint_kind(EndpointKind::Bitski);
+    assert_eq!(network.endpoint_kind, EndpointKind::Bitski);
 }
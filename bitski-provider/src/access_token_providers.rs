@@ -1,9 +1,14 @@
+use crate::secret::Secret;
 use anyhow::Error;
 use oauth2::basic::BasicClient;
 use oauth2::{AuthUrl, ClientId, ClientSecret, Scope, TokenResponse, TokenUrl};
 use std::fmt::Debug;
 use web3::futures::future::BoxFuture;
 
+/// The Bitski OAuth token endpoint, used unless another is set with
+/// [ClientCredentialsAccessTokenProvider::new_with_token_url].
+pub const DEFAULT_TOKEN_URL: &str = "https://account.bitski.com/oauth2/token";
+
 pub trait AccessTokenProvider: Debug {
     fn get_access_token(&self) -> BoxFuture<'static, Result<String, Error>>;
 }
@@ -16,14 +21,26 @@ pub struct ClientCredentialsAccessTokenProvider {
 
 impl ClientCredentialsAccessTokenProvider {
     pub fn new(client_id: String, client_secret: String, scopes: Option<Vec<String>>) -> Self {
+        Self::new_with_token_url(client_id, client_secret, scopes, DEFAULT_TOKEN_URL)
+            .expect("default token URL is valid")
+    }
+
+    /// Requests tokens from `token_url` instead of the Bitski token endpoint, for example a local
+    /// server in tests.
+    pub fn new_with_token_url(
+        client_id: String,
+        client_secret: String,
+        scopes: Option<Vec<String>>,
+        token_url: &str,
+    ) -> Result<Self, Error> {
         let client = BasicClient::new(
             ClientId::new(client_id),
             Some(ClientSecret::new(client_secret)),
-            AuthUrl::new("https://account.bitski.com/oauth2/auth".to_string()).unwrap(),
-            Some(TokenUrl::new("https://account.bitski.com/oauth2/token".to_string()).unwrap()),
+            AuthUrl::new("https://account.bitski.com/oauth2/auth".to_string())?,
+            Some(TokenUrl::new(token_url.to_string())?),
         );
 
-        Self { client, scopes }
+        Ok(Self { client, scopes })
     }
 
     async fn get_access_token(
@@ -67,6 +84,13 @@ impl AccessTokenProvider for ClientCredentialsAccessTokenProvider {
     }
 }
 
+impl AccessTokenProvider for Secret {
+    fn get_access_token(&self) -> BoxFuture<'static, Result<String, Error>> {
+        Box::pin(std::future::ready(Ok(self.expose().to_string())))
+    }
+}
+
+/// Prefer [Secret], since the `Debug` output of a `String` includes the token.
 impl AccessTokenProvider for String {
     fn get_access_token(&self) -> BoxFuture<'static, Result<String, Error>> {
         Box::pin(std::future::ready(Ok(self.clone())))
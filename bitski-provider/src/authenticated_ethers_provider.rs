@@ -1,9 +1,9 @@
 use crate::access_token_providers::AccessTokenProvider;
-use crate::USER_AGENT;
-use bitski_chain_models::networks::Network;
+use crate::error::BitskiError;
+use crate::http_client::HttpClientOptions;
+use crate::secret::{sensitive_header, Secret};
+use bitski_chain_models::networks::{EndpointKind, Network};
 use ethers::prelude::*;
-use reqwest::header::HeaderValue;
-use reqwest::Client;
 use reqwest::{header, Url};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -13,8 +13,9 @@ use std::sync::Arc;
 #[derive(Clone, Debug)]
 pub struct AuthenticatedEthersProvider {
     pub network: Network,
-    pub client_id: String,
+    pub client_id: Secret,
     pub auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
+    pub http_options: HttpClientOptions,
 }
 
 impl AuthenticatedEthersProvider {
@@ -25,27 +26,28 @@ impl AuthenticatedEthersProvider {
     ) -> Self {
         AuthenticatedEthersProvider {
             network,
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
             auth_token_provider,
+            http_options: HttpClientOptions::default(),
         }
     }
 
+    /// Builds the HTTP clients for requests with `options`.
+    pub fn with_http_options(mut self, options: HttpClientOptions) -> Self {
+        self.http_options = options;
+        self
+    }
+
     async fn send<I: Debug + Serialize + Send + Sync, T: DeserializeOwned + Send>(
         &self,
         method: &str,
         params: I,
-    ) -> Result<T, HttpClientError> {
+    ) -> Result<T, BitskiError> {
         let access_token = self
             .auth_token_provider
             .get_access_token()
             .await
-            .map_err(|error| {
-                HttpClientError::JsonRpcError(JsonRpcError {
-                    code: 403,
-                    message: format!("{error}"),
-                    data: None,
-                })
-            })?;
+            .map_err(|error| BitskiError::Auth(error.to_string()))?;
         self.send_with_auth(method, params, access_token).await
     }
 
@@ -54,54 +56,41 @@ impl AuthenticatedEthersProvider {
         method: &str,
         params: I,
         token: String,
-    ) -> Result<T, HttpClientError> {
-        let auth_header_value = format!("Bearer {}", token).parse().map_err(|error| {
-            HttpClientError::JsonRpcError(JsonRpcError {
-                code: 403,
-                message: format!("{error}"),
-                data: None,
-            })
-        })?;
-
+    ) -> Result<T, BitskiError> {
+        let auth_header_value = sensitive_header(&format!("Bearer {}", token))
+            .map_err(|_error| BitskiError::Auth("invalid access token".to_string()))?;
         let mut headers = header::HeaderMap::new();
         headers.insert(header::AUTHORIZATION, auth_header_value);
-        headers.insert("X-API-Key", HeaderValue::from_str(&self.client_id).unwrap());
-        headers.insert(
-            header::USER_AGENT,
-            HeaderValue::from_str(&USER_AGENT).unwrap(),
-        );
+        if self.network.endpoint_kind == EndpointKind::Bitski {
+            let api_key = self
+                .client_id
+                .header_value()
+                .map_err(|_error| BitskiError::Config("invalid API key".to_string()))?;
+            headers.insert("X-API-Key", api_key);
+        }
 
-        let client = Client::builder()
-            .default_headers(headers)
-            .build()
-            .map_err(|error| {
-                HttpClientError::JsonRpcError(JsonRpcError {
-                    code: 500,
-                    message: format!("{error}"),
-                    data: None,
-                })
-            })?;
+        let client = self.http_options.build_client(headers)?;
 
         let url: Url = self
             .network
             .rpc_url
             .parse()
-            .expect("Failed to parse RPC URL");
+            .map_err(|error| BitskiError::Config(format!("invalid RPC URL: {}", error)))?;
 
         let transport = Http::new_with_client(url, client);
-        JsonRpcClient::request(&transport, method, params).await
+        Ok(JsonRpcClient::request(&transport, method, params).await?)
     }
 }
 
 #[async_trait::async_trait]
 impl JsonRpcClient for AuthenticatedEthersProvider {
-    type Error = HttpClientError;
+    type Error = BitskiError;
 
     async fn request<T: Debug + Serialize + Send + Sync, R: DeserializeOwned + Send>(
         &self,
         method: &str,
         params: T,
-    ) -> Result<R, HttpClientError> {
+    ) -> Result<R, BitskiError> {
         self.send(method, params).await
     }
 }
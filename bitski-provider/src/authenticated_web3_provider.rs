@@ -1,21 +1,23 @@
 use crate::access_token_providers::AccessTokenProvider;
-use crate::USER_AGENT;
-use bitski_chain_models::networks::Network;
+use crate::error::BitskiError;
+use crate::http_client::HttpClientOptions;
+use crate::secret::{sensitive_header, Secret};
+use bitski_chain_models::networks::{EndpointKind, Network};
 use jsonrpc_core::futures::future::BoxFuture;
 use jsonrpc_core::Call;
-use reqwest::header::HeaderValue;
-use reqwest::{header, Client, Url};
+use reqwest::{header, Url};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use web3::futures::{FutureExt, TryFutureExt};
+use web3::futures::{future, FutureExt, TryFutureExt};
 use web3::transports::Http;
 use web3::{helpers, BatchTransport, RequestId, Transport};
 
 #[derive(Clone, Debug)]
 pub struct AuthenticatedWeb3Provider {
     pub network: Network,
-    pub client_id: String,
+    pub client_id: Secret,
     pub auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
+    pub http_options: HttpClientOptions,
     id: Arc<AtomicUsize>,
 }
 
@@ -27,32 +29,51 @@ impl AuthenticatedWeb3Provider {
     ) -> Self {
         AuthenticatedWeb3Provider {
             network,
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
             auth_token_provider,
+            http_options: HttpClientOptions::default(),
             id: Arc::new(AtomicUsize::new(0)),
         }
     }
 
+    /// Builds the HTTP clients for requests with `options`.
+    pub fn with_http_options(mut self, options: HttpClientOptions) -> Self {
+        self.http_options = options;
+        self
+    }
+
+    fn url(&self) -> Result<Url, BitskiError> {
+        self.network
+            .rpc_url
+            .parse()
+            .map_err(|error| BitskiError::Config(format!("invalid RPC URL: {}", error)))
+    }
+
+    /// Sends a call with the access token, and with the API key for [EndpointKind::Bitski]
+    /// networks.
     async fn send_with_auth(
         url: Url,
-        client_id: String,
+        endpoint_kind: EndpointKind,
+        client_id: Secret,
+        http_options: HttpClientOptions,
         token: String,
         id: RequestId,
         request: Call,
     ) -> Result<jsonrpc_core::Value, web3::error::Error> {
-        let auth_header_value = format!("Bearer {}", token)
-            .parse()
-            .map_err(|_error| web3::error::Error::Internal)?;
-
+        let auth_header_value = sensitive_header(&format!("Bearer {}", token))
+            .map_err(|_error| BitskiError::Auth("invalid access token".to_string()))?;
         let mut headers = header::HeaderMap::new();
         headers.insert(header::AUTHORIZATION, auth_header_value);
-        headers.insert("X-API-Key", HeaderValue::from_str(&client_id).unwrap());
+        if endpoint_kind == EndpointKind::Bitski {
+            let api_key = client_id
+                .header_value()
+                .map_err(|_error| BitskiError::Config("invalid API key".to_string()))?;
+            headers.insert("X-API-Key", api_key);
+        }
 
-        let client = Client::builder()
-            .user_agent(USER_AGENT.clone())
-            .default_headers(headers)
-            .build()
-            .map_err(|_error| web3::error::Error::Internal)?;
+        let client = http_options
+            .build_client(headers)
+            .map_err(BitskiError::from)?;
 
         let transport = Http::with_client(client, url);
         transport.send(id, request).await
@@ -60,14 +81,24 @@ impl AuthenticatedWeb3Provider {
 
     async fn convert_to_batch(
         url: Url,
-        client_id: String,
+        endpoint_kind: EndpointKind,
+        client_id: Secret,
+        http_options: HttpClientOptions,
         requests: Vec<(RequestId, Call)>,
         token: String,
     ) -> web3::error::Result<Vec<web3::error::Result<jsonrpc_core::Value>>> {
         let mut results = Vec::new();
         for (id, call) in requests {
-            let result =
-                Self::send_with_auth(url.clone(), client_id.clone(), token.clone(), id, call).await;
+            let result = Self::send_with_auth(
+                url.clone(),
+                endpoint_kind,
+                client_id.clone(),
+                http_options.clone(),
+                token.clone(),
+                id,
+                call,
+            )
+            .await;
             results.push(result);
         }
         Ok(results)
@@ -84,18 +115,28 @@ impl Transport for AuthenticatedWeb3Provider {
     }
 
     fn send(&self, id: RequestId, request: Call) -> Self::Out {
-        let url = self
-            .network
-            .rpc_url
-            .parse()
-            .map_err(|_error| web3::error::Error::Internal)
-            .unwrap();
+        let url = match self.url() {
+            Ok(url) => url,
+            Err(error) => return future::ready(Err(error.into())).boxed(),
+        };
+        let endpoint_kind = self.network.endpoint_kind;
         let client_id = self.client_id.clone();
+        let http_options = self.http_options.clone();
 
         self.auth_token_provider
             .get_access_token()
-            .map_err(|_error| web3::error::Error::Internal)
-            .and_then(move |token| Self::send_with_auth(url, client_id, token, id, request))
+            .map_err(|error| BitskiError::Auth(error.to_string()).into())
+            .and_then(move |token| {
+                Self::send_with_auth(
+                    url,
+                    endpoint_kind,
+                    client_id,
+                    http_options,
+                    token,
+                    id,
+                    request,
+                )
+            })
             .boxed()
     }
 }
@@ -108,21 +149,29 @@ impl BatchTransport for AuthenticatedWeb3Provider {
     where
         T: IntoIterator<Item = (RequestId, Call)>,
     {
-        let url = self
-            .network
-            .rpc_url
-            .parse()
-            .map_err(|_error| web3::error::Error::Internal)
-            .unwrap();
+        let url = match self.url() {
+            Ok(url) => url,
+            Err(error) => return future::ready(Err(error.into())).boxed(),
+        };
+        let endpoint_kind = self.network.endpoint_kind;
         let client_id = self.client_id.clone();
+        let http_options = self.http_options.clone();
 
         let requests_vec = requests.into_iter().collect();
 
         self.auth_token_provider
             .get_access_token()
-            .map_err(|_error| web3::error::Error::Internal)
+            .map_err(|error| BitskiError::Auth(error.to_string()).into())
             .and_then(move |token: String| {
-                Self::convert_to_batch(url, client_id, requests_vec, token).boxed()
+                Self::convert_to_batch(
+                    url,
+                    endpoint_kind,
+                    client_id,
+                    http_options,
+                    requests_vec,
+                    token,
+                )
+                .boxed()
             })
             .boxed()
     }
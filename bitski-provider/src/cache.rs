@@ -0,0 +1,532 @@
+use cached::{Cached, SizedCache};
+use jsonrpc_core::Call;
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use web3::futures::future::{self, BoxFuture};
+use web3::{BatchTransport, RequestId, Transport};
+
+/// Methods whose results may be cached, depending on their params and result.
+static CACHEABLE_METHODS: &[&str] = &[
+    "eth_chainId",
+    "net_version",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getTransactionReceipt",
+];
+
+/// A store for cached JSON-RPC results.
+pub trait ResponseStore: Debug + Send + Sync {
+    fn get(&self, key: &str) -> Option<Value>;
+    fn insert(&self, key: String, value: Value);
+}
+
+/// A bounded in-memory store that evicts the least recently used results.
+#[derive(Debug)]
+pub struct LruStore {
+    cache: Mutex<SizedCache<String, Value>>,
+}
+
+impl LruStore {
+    pub fn new(capacity: usize) -> Self {
+        LruStore {
+            cache: Mutex::new(SizedCache::with_size(capacity)),
+        }
+    }
+}
+
+impl ResponseStore for LruStore {
+    fn get(&self, key: &str) -> Option<Value> {
+        let mut cache = self.cache.lock().expect("cache lock poisoned");
+        cache.cache_get(key).cloned()
+    }
+
+    fn insert(&self, key: String, value: Value) {
+        let mut cache = self.cache.lock().expect("cache lock poisoned");
+        cache.cache_set(key, value);
+    }
+}
+
+/// How a [ResponseCache] decides that a block can no longer be replaced by a reorg.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finality {
+    /// A block is final once it is at least this many blocks behind the highest block number the
+    /// cache has seen in a result.
+    Depth(u64),
+    /// A block is final once it is no newer than the node's `finalized` block. The caching
+    /// providers call `eth_getBlockByNumber("finalized")` when a result is newer than the last
+    /// finalized block they have seen.
+    Finalized,
+}
+
+/// Caches the results of JSON-RPC calls that can't change.
+///
+/// `eth_chainId`, `net_version` and `eth_getBlockByHash` are always cached. Blocks by number and
+/// transaction receipts are only cached once their block is final, as decided by the cache's
+/// [Finality].
+///
+/// Results are stored under keys that include the cache's chain id, so one store can be shared by
+/// the caches of several networks. Use [ResponseCache::for_chain] to get such a cache.
+#[derive(Clone, Debug)]
+pub struct ResponseCache {
+    store: Arc<dyn ResponseStore>,
+    chain_id: u64,
+    finality: Finality,
+    latest_block: Arc<AtomicU64>,
+    // one more than the number of the latest finalized block seen, or 0 if none has been seen
+    finalized_end: Arc<AtomicU64>,
+}
+
+impl ResponseCache {
+    /// A cache for the chain `chain_id` that treats blocks at least `finality_depth` blocks
+    /// behind the highest block seen as final.
+    pub fn new(store: Arc<dyn ResponseStore>, chain_id: u64, finality_depth: u64) -> Self {
+        ResponseCache {
+            store,
+            chain_id,
+            finality: Finality::Depth(finality_depth),
+            latest_block: Arc::new(AtomicU64::new(0)),
+            finalized_end: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Decides which blocks are final with `finality` instead of the depth given to `new`.
+    pub fn with_finality(mut self, finality: Finality) -> Self {
+        self.finality = finality;
+        self
+    }
+
+    /// A cache for the chain `chain_id` backed by an [LruStore] holding up to `capacity` results.
+    pub fn in_memory(chain_id: u64, capacity: usize) -> Self {
+        Self::new(Arc::new(LruStore::new(capacity)), chain_id, 64)
+    }
+
+    /// Returns a cache for the chain `chain_id` that shares this cache's store and finality, and
+    /// tracks the blocks of its own chain.
+    pub fn for_chain(&self, chain_id: u64) -> Self {
+        ResponseCache {
+            store: self.store.clone(),
+            chain_id,
+            finality: self.finality,
+            latest_block: Arc::new(AtomicU64::new(0)),
+            finalized_end: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Returns the cached result of a call, if any.
+    pub fn get(&self, method: &str, params: &Value) -> Option<Value> {
+        if !CACHEABLE_METHODS.contains(&method) {
+            return None;
+        }
+        self.store.get(&self.key(method, params))
+    }
+
+    /// Records the result of a call, caching it if it can't change.
+    pub fn record(&self, method: &str, params: &Value, result: &Value) {
+        let number = match method {
+            "eth_blockNumber" => result.as_str().and_then(parse_quantity),
+            "eth_getBlockByHash" | "eth_getBlockByNumber" => {
+                result["number"].as_str().and_then(parse_quantity)
+            }
+            _ => None,
+        };
+        if let Some(number) = number {
+            self.latest_block.fetch_max(number, Ordering::AcqRel);
+            if method == "eth_getBlockByNumber" && params[0] == "finalized" {
+                self.finalized_end.fetch_max(number + 1, Ordering::AcqRel);
+            }
+        }
+
+        if self.is_immutable(method, params, result) {
+            self.store.insert(self.key(method, params), result.clone());
+        }
+    }
+
+    fn key(&self, method: &str, params: &Value) -> String {
+        format!("{}:{}:{}", self.chain_id, method, params)
+    }
+
+    /// Returns whether the finalized block must be fetched to decide if a result can be cached.
+    fn needs_finalized(&self, method: &str, params: &Value, result: &Value) -> bool {
+        self.finality == Finality::Finalized
+            && final_block(method, params, result).map_or(false, |number| !self.is_final(number))
+    }
+
+    fn is_immutable(&self, method: &str, params: &Value, result: &Value) -> bool {
+        match method {
+            "eth_chainId" | "net_version" => true,
+            "eth_getBlockByHash" => !result.is_null(),
+            _ => final_block(method, params, result).map_or(false, |number| self.is_final(number)),
+        }
+    }
+
+    fn is_final(&self, block_number: u64) -> bool {
+        match self.finality {
+            Finality::Depth(depth) => {
+                block_number + depth <= self.latest_block.load(Ordering::Acquire)
+            }
+            Finality::Finalized => block_number < self.finalized_end.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Returns the number of the block whose finality decides if a result can be cached.
+fn final_block(method: &str, params: &Value, result: &Value) -> Option<u64> {
+    match method {
+        "eth_getBlockByNumber" if !result.is_null() => params[0].as_str().and_then(parse_quantity),
+        "eth_getTransactionReceipt" => result["blockNumber"].as_str().and_then(parse_quantity),
+        _ => None,
+    }
+}
+
+fn finalized_params() -> Value {
+    json!(["finalized", false])
+}
+
+/// Fetches the finalized block and records it in `cache`. A failure leaves the results that
+/// needed it uncached.
+fn fetch_finalized<T: Transport>(transport: &T, cache: &ResponseCache) -> impl Future<Output = ()> {
+    let params = finalized_params();
+    let (id, request) = transport.prepare(
+        "eth_getBlockByNumber",
+        params.as_array().cloned().unwrap_or_default(),
+    );
+    let response = transport.send(id, request);
+    let cache = cache.clone();
+    async move {
+        if let Ok(block) = response.await {
+            cache.record("eth_getBlockByNumber", &params, &block);
+        }
+    }
+}
+
+fn parse_quantity(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+/// A web3 transport that serves immutable results from a [ResponseCache].
+///
+/// Calls in a batch are served from the cache too, and only the calls that missed the cache are
+/// sent as a batch.
+#[derive(Clone, Debug)]
+pub struct CachingWeb3Provider<T> {
+    pub transport: T,
+    pub cache: ResponseCache,
+}
+
+impl<T> CachingWeb3Provider<T> {
+    pub fn new(transport: T, cache: ResponseCache) -> Self {
+        CachingWeb3Provider { transport, cache }
+    }
+}
+
+impl<T> Transport for CachingWeb3Provider<T>
+where
+    T: Transport + Send + 'static,
+    T::Out: Send + 'static,
+{
+    type Out = BoxFuture<'static, web3::error::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let Call::MethodCall(method_call) = &request else {
+            return Box::pin(self.transport.send(id, request));
+        };
+        let method = method_call.method.clone();
+        let params = serde_json::to_value(&method_call.params).unwrap_or_default();
+        if let Some(result) = self.cache.get(&method, &params) {
+            return Box::pin(future::ready(Ok(result)));
+        }
+
+        let cache = self.cache.clone();
+        let transport = self.transport.clone();
+        let response = self.transport.send(id, request);
+        Box::pin(async move {
+            let result = response.await?;
+            if cache.needs_finalized(&method, &params, &result) {
+                let fetch = fetch_finalized(&transport, &cache);
+                fetch.await;
+            }
+            cache.record(&method, &params, &result);
+            Ok(result)
+        })
+    }
+}
+
+impl<T> BatchTransport for CachingWeb3Provider<T>
+where
+    T: BatchTransport + Send + 'static,
+    T::Out: Send + 'static,
+    T::Batch: Send + 'static,
+{
+    type Batch = BoxFuture<'static, web3::error::Result<Vec<web3::error::Result<Value>>>>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, Call)>,
+    {
+        // the method, params and cached result of each call, in order
+        let mut calls = Vec::new();
+        let mut uncached = Vec::new();
+        for (id, request) in requests {
+            let call = match &request {
+                Call::MethodCall(method_call) => Some((
+                    method_call.method.clone(),
+                    serde_json::to_value(&method_call.params).unwrap_or_default(),
+                )),
+                _ => None,
+            };
+            let cached = call
+                .as_ref()
+                .and_then(|(method, params)| self.cache.get(method, params));
+            if cached.is_none() {
+                uncached.push((id, request));
+            }
+            calls.push((call, cached));
+        }
+
+        let cache = self.cache.clone();
+        let transport = self.transport.clone();
+        let response = match uncached.is_empty() {
+            true => None,
+            false => Some(self.transport.send_batch(uncached)),
+        };
+        Box::pin(async move {
+            let mut responses = match response {
+                Some(response) => response.await?.into_iter(),
+                None => Vec::new().into_iter(),
+            };
+            let mut results = Vec::with_capacity(calls.len());
+            let mut needs_finalized = false;
+            for (call, cached) in calls {
+                let Some(result) = cached else {
+                    let result = responses.next().ok_or_else(|| {
+                        web3::Error::InvalidResponse("missing batch response".to_string())
+                    })?;
+                    if let (Some((method, params)), Ok(result)) = (&call, &result) {
+                        needs_finalized |= cache.needs_finalized(method, params, result);
+                    }
+                    results.push((call, result));
+                    continue;
+                };
+                results.push((None, Ok(result)));
+            }
+
+            if needs_finalized {
+                let fetch = fetch_finalized(&transport, &cache);
+                fetch.await;
+            }
+            Ok(results
+                .into_iter()
+                .map(|(call, result)| {
+                    if let (Some((method, params)), Ok(result)) = (&call, &result) {
+                        cache.record(method, params, result);
+                    }
+                    result
+                })
+                .collect())
+        })
+    }
+}
+
+/// An ethers client that serves immutable results from a [ResponseCache].
+#[cfg(feature = "ethers")]
+#[derive(Clone, Debug)]
+pub struct CachingEthersProvider<C> {
+    pub client: C,
+    pub cache: ResponseCache,
+}
+
+#[cfg(feature = "ethers")]
+impl<C> CachingEthersProvider<C> {
+    pub fn new(client: C, cache: ResponseCache) -> Self {
+        CachingEthersProvider { client, cache }
+    }
+}
+
+#[cfg(feature = "ethers")]
+#[async_trait::async_trait]
+impl<C: ethers::prelude::JsonRpcClient> ethers::prelude::JsonRpcClient
+    for CachingEthersProvider<C>
+{
+    type Error = ethers::prelude::ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + serde::Serialize + Send + Sync,
+        R: serde::de::DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        if let Some(result) = self.cache.get(method, &params) {
+            return Ok(serde_json::from_value(result)?);
+        }
+
+        let result: Value = self
+            .client
+            .request(method, &params)
+            .await
+            .map_err(Into::into)?;
+        if self.cache.needs_finalized(method, &params, &result) {
+            let params = finalized_params();
+            // a failure leaves the result uncached
+            let block = self
+                .client
+                .request::<_, Value>("eth_getBlockByNumber", &params)
+                .await;
+            if let Ok(block) = block {
+                self.cache.record("eth_getBlockByNumber", &params, &block);
+            }
+        }
+        self.cache.record(method, &params, &result);
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachingWeb3Provider, Finality, ResponseCache};
+    use jsonrpc_core::Call;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use web3::futures::future::{self, BoxFuture};
+    use web3::futures::FutureExt;
+    use web3::{helpers, BatchTransport, RequestId, Transport};
+
+    /// Answers every call with the same block, or block 0x20 for the finalized block, counting the
+    /// calls it receives.
+    #[derive(Clone, Debug, Default)]
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Transport for Counting {
+        type Out = BoxFuture<'static, web3::error::Result<Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            (0, helpers::build_request(0, method, params))
+        }
+
+        fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+            self.0.fetch_add(1, Ordering::AcqRel);
+            let Call::MethodCall(call) = request else {
+                panic!("unexpected request");
+            };
+            let params: Vec<Value> = call.params.parse().unwrap_or_default();
+            let result = match call.method.as_str() {
+                "eth_chainId" => json!("0x1"),
+                "eth_blockNumber" => json!("0x64"),
+                _ if params.first() == Some(&json!("finalized")) => json!({"number": "0x20"}),
+                _ => json!({"number": "0x10"}),
+            };
+            Box::pin(future::ready(Ok(result)))
+        }
+    }
+
+    impl BatchTransport for Counting {
+        type Batch = BoxFuture<'static, web3::error::Result<Vec<web3::error::Result<Value>>>>;
+
+        fn send_batch<T>(&self, requests: T) -> Self::Batch
+        where
+            T: IntoIterator<Item = (RequestId, Call)>,
+        {
+            let responses = requests
+                .into_iter()
+                .map(|(id, request)| self.send(id, request));
+            Box::pin(future::join_all(responses).map(Ok))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_immutable_results() {
+        let counting = Counting::default();
+        let provider = CachingWeb3Provider::new(counting.clone(), ResponseCache::in_memory(1, 16));
+
+        provider.execute("eth_chainId", vec![]).await.unwrap();
+        provider.execute("eth_chainId", vec![]).await.unwrap();
+        assert_eq!(counting.0.load(Ordering::Acquire), 1);
+
+        // block 0x10 isn't final until a head at least 64 blocks later has been seen
+        let params = vec![json!("0x10"), json!(false)];
+        provider
+            .execute("eth_getBlockByNumber", params.clone())
+            .await
+            .unwrap();
+        provider
+            .execute("eth_getBlockByNumber", params.clone())
+            .await
+            .unwrap();
+        assert_eq!(counting.0.load(Ordering::Acquire), 3);
+
+        provider.execute("eth_blockNumber", vec![]).await.unwrap();
+        provider
+            .execute("eth_getBlockByNumber", params.clone())
+            .await
+            .unwrap();
+        provider
+            .execute("eth_getBlockByNumber", params)
+            .await
+            .unwrap();
+        assert_eq!(counting.0.load(Ordering::Acquire), 5);
+    }
+
+    #[tokio::test]
+    async fn test_cache_finalized_blocks() {
+        let counting = Counting::default();
+        let cache = ResponseCache::in_memory(1, 16).with_finality(Finality::Finalized);
+        let provider = CachingWeb3Provider::new(counting.clone(), cache);
+
+        // block 0x10 is cached once the finalized block 0x20 has been fetched
+        let params = vec![json!("0x10"), json!(false)];
+        provider
+            .execute("eth_getBlockByNumber", params.clone())
+            .await
+            .unwrap();
+        assert_eq!(counting.0.load(Ordering::Acquire), 2);
+        provider
+            .execute("eth_getBlockByNumber", params)
+            .await
+            .unwrap();
+        assert_eq!(counting.0.load(Ordering::Acquire), 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_batches() {
+        let counting = Counting::default();
+        let provider = CachingWeb3Provider::new(counting.clone(), ResponseCache::in_memory(1, 16));
+        provider.execute("eth_chainId", vec![]).await.unwrap();
+
+        // only the calls that miss the cache are sent, and the results keep their order
+        let requests = ["eth_chainId", "eth_blockNumber", "net_version"]
+            .map(|method| provider.prepare(method, vec![]));
+        let results = provider.send_batch(requests).await.unwrap();
+        let results: Vec<_> = results.into_iter().map(Result::unwrap).collect();
+        assert_eq!(results[..2], [json!("0x1"), json!("0x64")]);
+        assert_eq!(counting.0.load(Ordering::Acquire), 3);
+
+        let requests =
+            ["eth_chainId", "net_version"].map(|method| provider.prepare(method, vec![]));
+        provider.send_batch(requests).await.unwrap();
+        assert_eq!(counting.0.load(Ordering::Acquire), 3);
+    }
+
+    #[tokio::test]
+    async fn test_shared_store() {
+        let mainnet = Counting::default();
+        let goerli = Counting::default();
+        let cache = ResponseCache::in_memory(1, 16);
+        let mainnet_provider = CachingWeb3Provider::new(mainnet.clone(), cache.clone());
+        let goerli_provider = CachingWeb3Provider::new(goerli.clone(), cache.for_chain(5));
+
+        for provider in [&mainnet_provider, &goerli_provider] {
+            provider.execute("eth_chainId", vec![]).await.unwrap();
+            provider.execute("eth_chainId", vec![]).await.unwrap();
+        }
+        // each network sent its own call, and was then served from its own entry
+        assert_eq!(mainnet.0.load(Ordering::Acquire), 1);
+        assert_eq!(goerli.0.load(Ordering::Acquire), 1);
+    }
+}
@@ -0,0 +1,521 @@
+use crate::error::BitskiError;
+#[cfg(feature = "ethers")]
+use crate::ethers_provider::RoutedJsonRpcClient;
+use crate::middleware::{Middleware, Next, RequestContext, ResponseFuture, Route};
+use jsonrpc_core::Call;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use web3::error::TransportError;
+use web3::futures::future::{self, BoxFuture};
+use web3::futures::FutureExt;
+use web3::signing::RecoveryError;
+use web3::{helpers, BatchTransport, RequestId, Transport};
+
+/// A recorded JSON-RPC call, stored as one line of a JSONL cassette.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub method: String,
+    pub params: Value,
+    pub route: Route,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// The response to a recorded call.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Result(Value),
+    RpcError {
+        code: i64,
+        message: String,
+        data: Option<Value>,
+    },
+    Error(RecordedError),
+}
+
+/// A failed call that didn't get a JSON-RPC error response, stored with its kind so that it is
+/// replayed as the same `web3::Error` variant.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordedError {
+    Unreachable,
+    Decoder {
+        message: String,
+    },
+    InvalidResponse {
+        message: String,
+    },
+    TransportCode {
+        status: u16,
+    },
+    TransportMessage {
+        message: String,
+    },
+    /// An I/O error, with the `Debug` name of its `io::ErrorKind`.
+    Io {
+        io_kind: String,
+    },
+    Recovery {
+        invalid_signature: bool,
+    },
+    Internal,
+}
+
+/// The I/O error kinds that are replayed as themselves. Any other kind is replayed as
+/// `io::ErrorKind::Other`.
+const IO_ERROR_KINDS: &[io::ErrorKind] = &[
+    io::ErrorKind::NotFound,
+    io::ErrorKind::PermissionDenied,
+    io::ErrorKind::ConnectionRefused,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::NotConnected,
+    io::ErrorKind::AddrInUse,
+    io::ErrorKind::AddrNotAvailable,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::WouldBlock,
+    io::ErrorKind::InvalidInput,
+    io::ErrorKind::InvalidData,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::Interrupted,
+    io::ErrorKind::UnexpectedEof,
+];
+
+impl Outcome {
+    fn new(result: &web3::error::Result<Value>) -> Self {
+        let error = match result {
+            Ok(value) => return Outcome::Result(value.clone()),
+            Err(web3::Error::Rpc(error)) => {
+                return Outcome::RpcError {
+                    code: error.code.code(),
+                    message: error.message.clone(),
+                    data: error.data.clone(),
+                }
+            }
+            Err(web3::Error::Unreachable) => RecordedError::Unreachable,
+            Err(web3::Error::Decoder(message)) => RecordedError::Decoder {
+                message: message.clone(),
+            },
+            Err(web3::Error::InvalidResponse(message)) => RecordedError::InvalidResponse {
+                message: message.clone(),
+            },
+            Err(web3::Error::Transport(TransportError::Code(status))) => {
+                RecordedError::TransportCode { status: *status }
+            }
+            Err(web3::Error::Transport(TransportError::Message(message))) => {
+                RecordedError::TransportMessage {
+                    message: message.clone(),
+                }
+            }
+            Err(web3::Error::Io(error)) => RecordedError::Io {
+                io_kind: format!("{:?}", error.kind()),
+            },
+            Err(web3::Error::Recovery(error)) => RecordedError::Recovery {
+                invalid_signature: matches!(error, RecoveryError::InvalidSignature),
+            },
+            Err(web3::Error::Internal) => RecordedError::Internal,
+        };
+        Outcome::Error(error)
+    }
+
+    fn into_result(self) -> web3::error::Result<Value> {
+        let error = match self {
+            Outcome::Result(value) => return Ok(value),
+            Outcome::RpcError {
+                code,
+                message,
+                data,
+            } => web3::Error::Rpc(jsonrpc_core::Error {
+                code: code.into(),
+                message,
+                data,
+            }),
+            Outcome::Error(RecordedError::Unreachable) => web3::Error::Unreachable,
+            Outcome::Error(RecordedError::Decoder { message }) => web3::Error::Decoder(message),
+            Outcome::Error(RecordedError::InvalidResponse { message }) => {
+                web3::Error::InvalidResponse(message)
+            }
+            Outcome::Error(RecordedError::TransportCode { status }) => {
+                web3::Error::Transport(TransportError::Code(status))
+            }
+            Outcome::Error(RecordedError::TransportMessage { message }) => {
+                web3::Error::Transport(TransportError::Message(message))
+            }
+            Outcome::Error(RecordedError::Io { io_kind }) => {
+                let kind = IO_ERROR_KINDS
+                    .iter()
+                    .find(|kind| format!("{:?}", kind) == io_kind)
+                    .copied()
+                    .unwrap_or(io::ErrorKind::Other);
+                web3::Error::Io(kind.into())
+            }
+            Outcome::Error(RecordedError::Recovery { invalid_signature }) => {
+                web3::Error::Recovery(match invalid_signature {
+                    true => RecoveryError::InvalidSignature,
+                    false => RecoveryError::InvalidMessage,
+                })
+            }
+            Outcome::Error(RecordedError::Internal) => web3::Error::Internal,
+        };
+        Err(error)
+    }
+}
+
+/// Appends every call and its response to a JSONL cassette.
+///
+/// Add it to a `BitskiWeb3Provider` with `with_middleware`, or wrap an ethers client in a
+/// [RecordingEthersProvider]. The cassette can be replayed with a [ReplayProvider].
+#[derive(Clone, Debug)]
+pub struct CassetteRecorder {
+    file: Arc<Mutex<File>>,
+}
+
+impl CassetteRecorder {
+    /// Creates the cassette at `path`, appending to it if it exists.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(CassetteRecorder {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Writes an interaction to the cassette.
+    pub fn record(&self, interaction: &Interaction) {
+        let mut file = self.file.lock().expect("cassette lock poisoned");
+        let written = serde_json::to_string(interaction)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(file, "{}", line));
+        if let Err(_error) = written {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Failed to record {}: {:?}", interaction.method, _error);
+        }
+    }
+}
+
+impl Middleware for CassetteRecorder {
+    fn handle(&self, request: Call, context: &RequestContext, next: Next<'_>) -> ResponseFuture {
+        let Call::MethodCall(method_call) = &request else {
+            return next.run(request);
+        };
+        let method = method_call.method.clone();
+        let params = serde_json::to_value(&method_call.params).unwrap_or_default();
+        let context = context.clone();
+        let recorder = self.clone();
+        let response = next.run(request);
+
+        async move {
+            let result = response.await;
+            recorder.record(&Interaction {
+                method,
+                params,
+                route: context.served_route(),
+                outcome: Outcome::new(&result),
+            });
+            result
+        }
+        .boxed()
+    }
+}
+
+/// An ethers client that records every call, its response and the route that served it with a
+/// [CassetteRecorder].
+#[cfg(feature = "ethers")]
+#[derive(Clone, Debug)]
+pub struct RecordingEthersProvider<C> {
+    pub client: C,
+    pub recorder: CassetteRecorder,
+}
+
+#[cfg(feature = "ethers")]
+impl<C> RecordingEthersProvider<C> {
+    pub fn new(client: C, recorder: CassetteRecorder) -> Self {
+        RecordingEthersProvider { client, recorder }
+    }
+}
+
+#[cfg(feature = "ethers")]
+#[async_trait::async_trait]
+impl<C> ethers::prelude::JsonRpcClient for RecordingEthersProvider<C>
+where
+    C: RoutedJsonRpcClient,
+    BitskiError: From<C::Error>,
+{
+    type Error = BitskiError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, BitskiError>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: serde::de::DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let (route, result) = self.client.request_routed(method, params.clone()).await;
+        let result = result.map_err(|error| web3::Error::from(BitskiError::from(error)));
+        self.recorder.record(&Interaction {
+            method: method.to_string(),
+            params,
+            route,
+            outcome: Outcome::new(&result),
+        });
+        Ok(serde_json::from_value(result?)?)
+    }
+}
+
+/// A provider that answers calls from a recorded cassette without sending them.
+///
+/// Each call is answered by the first unused interaction with the same method and params, so
+/// repeated calls are answered in the order they were recorded.
+#[derive(Clone, Debug)]
+pub struct ReplayProvider {
+    interactions: Arc<Mutex<Vec<Option<Interaction>>>>,
+    id: Arc<AtomicUsize>,
+}
+
+impl ReplayProvider {
+    /// Loads the cassette at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut interactions = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                interactions.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self::new(interactions))
+    }
+
+    pub fn new(interactions: Vec<Interaction>) -> Self {
+        ReplayProvider {
+            interactions: Arc::new(Mutex::new(interactions.into_iter().map(Some).collect())),
+            id: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Answers a call to `method` with `params` from the cassette.
+    pub fn respond(&self, method: &str, params: &Value) -> Result<Value, BitskiError> {
+        Ok(self.replay(method, params)?)
+    }
+
+    /// Answers a call with the recorded `web3::Error` as it was returned to the recorder.
+    fn replay(&self, method: &str, params: &Value) -> web3::error::Result<Value> {
+        let mut interactions = self.interactions.lock().expect("cassette lock poisoned");
+        let interaction = interactions.iter_mut().find(|interaction| {
+            matches!(interaction, Some(interaction) if interaction.method == method && interaction.params == *params)
+        });
+        match interaction.and_then(Option::take) {
+            Some(interaction) => interaction.outcome.into_result(),
+            None => Err(web3::Error::Transport(TransportError::Message(format!(
+                "no recorded response for {}({})",
+                method, params
+            )))),
+        }
+    }
+
+    fn respond_to_call(&self, request: Call) -> web3::error::Result<Value> {
+        let Call::MethodCall(method_call) = request else {
+            return Err(web3::Error::Internal);
+        };
+        let params = serde_json::to_value(&method_call.params)?;
+        self.replay(&method_call.method, &params)
+    }
+}
+
+impl Transport for ReplayProvider {
+    type Out = BoxFuture<'static, web3::error::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.id.fetch_add(1, Ordering::AcqRel);
+        let request = helpers::build_request(id, method, params);
+        (id, request)
+    }
+
+    fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+        future::ready(self.respond_to_call(request)).boxed()
+    }
+}
+
+impl BatchTransport for ReplayProvider {
+    type Batch = BoxFuture<'static, web3::error::Result<Vec<web3::error::Result<Value>>>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let results = requests
+            .into_iter()
+            .map(|(_id, request)| self.respond_to_call(request))
+            .collect();
+        future::ready(Ok(results)).boxed()
+    }
+}
+
+#[cfg(feature = "ethers")]
+#[async_trait::async_trait]
+impl ethers::prelude::JsonRpcClient for ReplayProvider {
+    type Error = BitskiError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, BitskiError>
+    where
+        T: std::fmt::Debug + Serialize + Send + Sync,
+        R: serde::de::DeserializeOwned + Send,
+    {
+        let result = self.respond(method, &serde_json::to_value(params)?)?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CassetteRecorder, Outcome, ReplayProvider};
+    use crate::middleware::{Middleware, Next, RequestContext, ResponseFuture, Route};
+    use crate::web3_provider::BitskiWeb3Provider;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use bitski_testkit::TestServer;
+    use jsonrpc_core::Call;
+    use serde_json::json;
+    use std::io;
+    use std::sync::Arc;
+    use web3::futures::{future, FutureExt};
+    use web3::Transport;
+
+    /// Answers `eth_blockNumber`, fails `eth_gasPrice` and `eth_chainId` without a JSON-RPC
+    /// error, and rejects every other call.
+    #[derive(Debug)]
+    struct Node;
+
+    impl Middleware for Node {
+        fn handle(&self, request: Call, _: &RequestContext, _: Next<'_>) -> ResponseFuture {
+            let Call::MethodCall(call) = request else {
+                panic!("unexpected request");
+            };
+            let result = match call.method.as_str() {
+                "eth_blockNumber" => Ok(json!("0x10")),
+                "eth_gasPrice" => Err(web3::Error::Io(io::ErrorKind::TimedOut.into())),
+                "eth_chainId" => Err(web3::Error::InvalidResponse("empty body".to_string())),
+                _ => Err(web3::Error::Rpc(jsonrpc_core::Error::method_not_found())),
+            };
+            future::ready(result).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let path =
+            std::env::temp_dir().join(format!("bitski-cassette-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let network = Network::new("https://api.bitski.com/v1/web3/mainnet", 1);
+        let provider = BitskiWeb3Provider::new(&network, &"client-id", Arc::new(()))
+            .with_middleware(CassetteRecorder::create(&path).unwrap())
+            .with_middleware(Node);
+        provider.execute("eth_blockNumber", vec![]).await.unwrap();
+        provider.execute("eth_accounts", vec![]).await.unwrap_err();
+        provider.execute("eth_gasPrice", vec![]).await.unwrap_err();
+        provider.execute("eth_chainId", vec![]).await.unwrap_err();
+
+        let replay = ReplayProvider::load(&path).unwrap();
+        let interactions = replay.interactions.lock().unwrap().clone();
+        assert_eq!(interactions[0].as_ref().unwrap().route, Route::Rest);
+        let second = interactions[1].as_ref().unwrap();
+        assert_eq!(second.route, Route::Authenticated);
+        assert!(matches!(
+            second.outcome,
+            Outcome::RpcError { code: -32601, .. }
+        ));
+
+        let result = replay.execute("eth_blockNumber", vec![]).await.unwrap();
+        assert_eq!(result, json!("0x10"));
+        assert!(matches!(
+            replay.execute("eth_accounts", vec![]).await,
+            Err(web3::Error::Rpc(error)) if error.code.code() == -32601
+        ));
+        assert!(matches!(
+            replay.execute("eth_gasPrice", vec![]).await,
+            Err(web3::Error::Io(error)) if error.kind() == io::ErrorKind::TimedOut
+        ));
+        assert!(matches!(
+            replay.execute("eth_chainId", vec![]).await,
+            Err(web3::Error::InvalidResponse(message)) if message == "empty body"
+        ));
+        // every recorded call has been replayed
+        assert!(replay.execute("eth_blockNumber", vec![]).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_served_route() {
+        let path = std::env::temp_dir().join(format!(
+            "bitski-cassette-route-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let server = TestServer::builder()
+            .with_response("eth_getTransactionByHash", json!(null))
+            .with_rest_methods(Vec::<String>::new())
+            .start()
+            .await
+            .unwrap();
+
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = BitskiWeb3Provider::new(&network, &"client-id", Arc::new(()))
+            .with_middleware(CassetteRecorder::create(&path).unwrap());
+        let params = vec![json!(format!("0x{:064x}", 1))];
+        provider
+            .execute("eth_getTransactionByHash", params)
+            .await
+            .unwrap();
+
+        // the REST route was planned, but the call was served by the JSON-RPC endpoint
+        let replay = ReplayProvider::load(&path).unwrap();
+        let interactions = replay.interactions.lock().unwrap().clone();
+        assert_eq!(interactions[0].as_ref().unwrap().route, Route::Http);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "ethers")]
+    #[tokio::test]
+    async fn test_record_ethers_route() {
+        use super::RecordingEthersProvider;
+        use crate::ethers_provider::BitskiEthersProvider;
+        use ethers::prelude::JsonRpcClient;
+
+        let path = std::env::temp_dir().join(format!(
+            "bitski-cassette-ethers-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let server = TestServer::builder()
+            .with_response("eth_blockNumber", json!("0x10"))
+            .start()
+            .await
+            .unwrap();
+
+        // a plain JSON-RPC node has no REST route
+        for kind in [EndpointKind::JsonRpc, EndpointKind::Bitski] {
+            let network = Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(kind);
+            let provider = RecordingEthersProvider::new(
+                BitskiEthersProvider::new(&network, &"client-id", Arc::new(())),
+                CassetteRecorder::create(&path).unwrap(),
+            );
+            let result: String = provider.request("eth_blockNumber", ()).await.unwrap();
+            assert_eq!(result, "0x10");
+        }
+
+        let replay = ReplayProvider::load(&path).unwrap();
+        let routes: Vec<_> = replay
+            .interactions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|interaction| interaction.as_ref().unwrap().route)
+            .collect();
+        assert_eq!(routes, [Route::Http, Route::Rest]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
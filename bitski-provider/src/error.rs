@@ -0,0 +1,291 @@
+use std::io;
+use std::time::Duration;
+use web3::error::TransportError;
+
+/// A JSON-RPC error response.
+#[cfg(feature = "ethers")]
+pub use ethers::providers::JsonRpcError;
+
+/// A JSON-RPC error response, with the same fields as the ethers type of the same name.
+#[cfg(not(feature = "ethers"))]
+#[derive(Clone, Debug, serde::Deserialize, thiserror::Error)]
+#[error("(code: {code}, message: {message}, data: {data:?})")]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    pub data: Option<serde_json::Value>,
+}
+
+/// The number of bytes of an unsuccessful response body that are kept in [BitskiError::Http].
+const MAX_ERROR_BODY: usize = 1024;
+
+/// An error returned by a Bitski provider.
+///
+/// Converting to a `web3::Error` and back gives the same error. Transport, HTTP status, JSON-RPC,
+/// timeout and decode errors become the matching `web3::Error` variants. Auth, rate-limit and
+/// configuration errors, and HTTP status errors with a body, have no such variant, so they become
+/// a `web3::Error::Io` that wraps the original error, with the `PermissionDenied`, `Other` and
+/// `InvalidInput` kinds. Cloning a
+/// `web3::Error` drops the wrapped error, and such a clone converts back to a transport error.
+/// Converting to an ethers `ProviderError` boxes the error, and JSON-RPC errors remain available
+/// through `as_error_response`.
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum BitskiError {
+    /// The request couldn't be sent, or the response couldn't be read.
+    #[error("transport error: {0}")]
+    Transport(String),
+    /// The server responded with an unsuccessful HTTP status. `body` is the start of the
+    /// response body, if it was read, for debugging.
+    #[error("HTTP status {status}")]
+    Http { status: u16, body: Option<String> },
+    /// The node responded with a JSON-RPC error.
+    #[error("JSON-RPC error {0}")]
+    JsonRpc(JsonRpcError),
+    /// An access token couldn't be obtained or used.
+    #[error("authentication failed: {0}")]
+    Auth(String),
+    /// The request didn't complete in time.
+    #[error("request timed out")]
+    Timeout,
+    /// The server rejected the request because too many requests were sent.
+    #[error("rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
+    /// The response couldn't be decoded.
+    #[error("failed to decode response: {0}")]
+    Decode(String),
+    /// The provider has an invalid URL, header or HTTP client option.
+    #[error("invalid configuration: {0}")]
+    Config(String),
+}
+
+impl BitskiError {
+    /// Returns the JSON-RPC error the node responded with, if any.
+    pub fn as_json_rpc(&self) -> Option<&JsonRpcError> {
+        match self {
+            BitskiError::JsonRpc(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Returns the error for an unsuccessful HTTP status.
+    pub(crate) fn from_status(status: u16, retry_after: Option<Duration>) -> Self {
+        match status {
+            429 => BitskiError::RateLimited { retry_after },
+            status => BitskiError::Http { status, body: None },
+        }
+    }
+
+    /// Keeps the start of `body` in an [BitskiError::Http] error.
+    pub(crate) fn with_body(self, body: &[u8]) -> Self {
+        match self {
+            BitskiError::Http { status, .. } if !body.is_empty() => {
+                let mut body = String::from_utf8_lossy(body).into_owned();
+                if body.len() > MAX_ERROR_BODY {
+                    let mut end = MAX_ERROR_BODY;
+                    while !body.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    body.truncate(end);
+                }
+                BitskiError::Http {
+                    status,
+                    body: Some(body),
+                }
+            }
+            error => error,
+        }
+    }
+
+    /// Returns the error for an unsuccessful HTTP response, reading its `Retry-After` header.
+    pub(crate) fn from_response(response: &reqwest::Response) -> Self {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok()?.parse().ok())
+            .map(Duration::from_secs);
+        Self::from_status(response.status().as_u16(), retry_after)
+    }
+}
+
+impl From<BitskiError> for web3::Error {
+    fn from(error: BitskiError) -> Self {
+        match error {
+            BitskiError::Transport(message) => {
+                web3::Error::Transport(TransportError::Message(message))
+            }
+            BitskiError::Http { status, body: None } => {
+                web3::Error::Transport(TransportError::Code(status))
+            }
+            error @ BitskiError::Http { .. } => {
+                web3::Error::Io(io::Error::new(io::ErrorKind::Other, error))
+            }
+            BitskiError::JsonRpc(error) => web3::Error::Rpc(jsonrpc_core::Error {
+                code: error.code.into(),
+                message: error.message,
+                data: error.data,
+            }),
+            BitskiError::Timeout => web3::Error::Io(io::ErrorKind::TimedOut.into()),
+            BitskiError::Decode(message) => web3::Error::Decoder(message),
+            error @ BitskiError::Auth(_) => {
+                web3::Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, error))
+            }
+            error @ BitskiError::RateLimited { .. } => {
+                web3::Error::Io(io::Error::new(io::ErrorKind::Other, error))
+            }
+            error @ BitskiError::Config(_) => {
+                web3::Error::Io(io::Error::new(io::ErrorKind::InvalidInput, error))
+            }
+        }
+    }
+}
+
+impl From<web3::Error> for BitskiError {
+    fn from(error: web3::Error) -> Self {
+        match error {
+            web3::Error::Rpc(error) => BitskiError::JsonRpc(JsonRpcError {
+                code: error.code.code(),
+                message: error.message,
+                data: error.data,
+            }),
+            web3::Error::Transport(TransportError::Code(429)) => {
+                BitskiError::RateLimited { retry_after: None }
+            }
+            web3::Error::Transport(TransportError::Code(status)) => {
+                BitskiError::Http { status, body: None }
+            }
+            web3::Error::Transport(TransportError::Message(message)) => {
+                BitskiError::Transport(message)
+            }
+            web3::Error::Io(error)
+                if error
+                    .get_ref()
+                    .map_or(false, |inner| inner.is::<BitskiError>()) =>
+            {
+                *error
+                    .into_inner()
+                    .and_then(|inner| inner.downcast().ok())
+                    .expect("checked by the guard")
+            }
+            web3::Error::Io(error) if error.kind() == io::ErrorKind::TimedOut => {
+                BitskiError::Timeout
+            }
+            web3::Error::Decoder(message) | web3::Error::InvalidResponse(message) => {
+                BitskiError::Decode(message)
+            }
+            web3::Error::Recovery(error) => BitskiError::Decode(error.to_string()),
+            error => BitskiError::Transport(error.to_string()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for BitskiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            BitskiError::Timeout
+        } else if error.is_builder() {
+            BitskiError::Config(error.to_string())
+        } else if error.is_decode() {
+            BitskiError::Decode(error.to_string())
+        } else if let Some(status) = error.status() {
+            BitskiError::from_status(status.as_u16(), None)
+        } else {
+            BitskiError::Transport(error.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for BitskiError {
+    fn from(error: serde_json::Error) -> Self {
+        BitskiError::Decode(error.to_string())
+    }
+}
+
+#[cfg(feature = "ethers")]
+mod ethers_impl {
+    use super::{BitskiError, JsonRpcError};
+    use ethers::providers::{HttpClientError, ProviderError, RpcError};
+
+    impl RpcError for BitskiError {
+        fn as_error_response(&self) -> Option<&JsonRpcError> {
+            self.as_json_rpc()
+        }
+
+        fn as_serde_error(&self) -> Option<&serde_json::Error> {
+            None
+        }
+    }
+
+    impl From<BitskiError> for ProviderError {
+        fn from(error: BitskiError) -> Self {
+            ProviderError::JsonRpcClientError(Box::new(error))
+        }
+    }
+
+    impl From<HttpClientError> for BitskiError {
+        fn from(error: HttpClientError) -> Self {
+            match error {
+                HttpClientError::ReqwestError(error) => error.into(),
+                HttpClientError::JsonRpcError(error) => BitskiError::JsonRpc(error),
+                HttpClientError::SerdeJson { err, text } => {
+                    BitskiError::Decode(format!("{}: {}", err, text))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitskiError, JsonRpcError};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn test_web3_round_trip() {
+        let errors = [
+            BitskiError::Transport("connection reset".to_string()),
+            BitskiError::Http {
+                status: 503,
+                body: None,
+            },
+            BitskiError::Http {
+                status: 502,
+                body: Some("<html>Bad Gateway</html>".to_string()),
+            },
+            BitskiError::JsonRpc(JsonRpcError {
+                code: 3,
+                message: "execution reverted".to_string(),
+                data: Some(json!("0x08c379a0")),
+            }),
+            BitskiError::Auth("Not signed in".to_string()),
+            BitskiError::Timeout,
+            BitskiError::RateLimited {
+                retry_after: Some(Duration::from_secs(1)),
+            },
+            BitskiError::Decode("expected value".to_string()),
+            BitskiError::Config("relative URL without a base".to_string()),
+        ];
+        for error in errors {
+            let round_trip = BitskiError::from(web3::Error::from(error.clone()));
+            assert_eq!(format!("{:?}", round_trip), format!("{:?}", error));
+        }
+
+        // node errors that resemble the wrapped variants keep their own kind
+        let unauthorized = web3::Error::Rpc(jsonrpc_core::Error {
+            code: 4100.into(),
+            message: "unauthorized".to_string(),
+            data: None,
+        });
+        assert!(matches!(
+            BitskiError::from(unauthorized),
+            BitskiError::JsonRpc(error) if error.code == 4100
+        ));
+        let message = web3::Error::Transport(web3::error::TransportError::Message(
+            "invalid configuration: proxy".to_string(),
+        ));
+        assert!(matches!(
+            BitskiError::from(message),
+            BitskiError::Transport(_)
+        ));
+    }
+}
@@ -1,37 +1,25 @@
 use crate::access_token_providers::AccessTokenProvider;
 use crate::authenticated_ethers_provider::AuthenticatedEthersProvider;
+use crate::error::BitskiError;
+use crate::http_client::HttpClientOptions;
+use crate::instrumentation::observe;
+use crate::middleware::{Route, Served};
+use crate::rest::RestCapabilities;
 use crate::rest_ethers_provider::RestEthersProvider;
-use crate::USER_AGENT;
-use bitski_chain_models::networks::Network;
+use crate::secret::Secret;
+use bitski_chain_models::networks::{EndpointKind, Network};
 use cached::proc_macro::cached;
-use ethers::prelude::{Http, HttpClientError, JsonRpcClient};
-use reqwest::header::HeaderValue;
-use reqwest::{header, Client, Url};
+use ethers::prelude::{Http, JsonRpcClient};
+use reqwest::{header, Url};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
 use std::fmt::Debug;
 use std::sync::Arc;
 
-static AUTH_METHODS: &[&str] = &[
-    "eth_sendTransaction",
-    "eth_accounts",
-    "eth_sign",
-    "personal_sign",
-    "eth_signTypedData",
-    "eth_signTypedData_v3",
-    "eth_signTypedData_v4",
-];
-
-static REST_METHODS: &[&str] = &[
-    "eth_blockNumber",
-    "eth_getBlockByNumber",
-    "net_version",
-    "eth_getLogs",
-];
-
 #[derive(Clone, Debug)]
 pub struct BitskiEthersProvider {
-    pub client_id: String,
+    pub client_id: Secret,
     pub authenticated_provider: Arc<AuthenticatedEthersProvider>,
     pub rest_provider: Arc<RestEthersProvider>,
     pub http_provider: Arc<Http>,
@@ -44,54 +32,152 @@ impl BitskiEthersProvider {
         auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
     ) -> Self {
         BitskiEthersProvider {
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
             authenticated_provider: Arc::new(AuthenticatedEthersProvider::new(
                 network.clone(),
                 client_id,
                 auth_token_provider,
             )),
             rest_provider: Arc::new(RestEthersProvider::new(network.clone(), client_id)),
-            http_provider: http_provider(network.clone(), client_id.to_string()),
+            http_provider: http_provider(network.clone(), Secret::new(client_id.to_string())),
         }
     }
+
+    /// Sends calls to `methods` to the REST route instead of the default REST methods.
+    pub fn with_rest_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.rest_provider = Arc::new(
+            RestEthersProvider::clone(&self.rest_provider)
+                .with_capabilities(RestCapabilities::new(methods)),
+        );
+        self
+    }
+
+    /// Builds the HTTP clients of every sub-provider with `options`.
+    pub fn with_http_options(mut self, options: HttpClientOptions) -> Self {
+        let network = self.rest_provider.network.clone();
+        self.authenticated_provider = Arc::new(
+            AuthenticatedEthersProvider::clone(&self.authenticated_provider)
+                .with_http_options(options.clone()),
+        );
+        self.rest_provider = Arc::new(
+            RestEthersProvider::clone(&self.rest_provider).with_http_options(options.clone()),
+        );
+        self.http_provider = build_http_provider(&network, &self.client_id, &options);
+        self
+    }
 }
 
 #[cached]
-fn http_provider(network: Network, client_id: String) -> Arc<Http> {
+fn http_provider(network: Network, client_id: Secret) -> Arc<Http> {
+    build_http_provider(&network, &client_id, &HttpClientOptions::default())
+}
+
+fn build_http_provider(
+    network: &Network,
+    client_id: &Secret,
+    options: &HttpClientOptions,
+) -> Arc<Http> {
     let url: Url = network.rpc_url.parse().expect("Failed to parse RPC URL");
 
     let mut headers = header::HeaderMap::new();
 
-    if url.as_str().contains("api.bitski.com") {
-        headers.insert("X-API-Key", HeaderValue::from_str(&client_id).unwrap());
+    if network.endpoint_kind == EndpointKind::Bitski {
+        headers.insert("X-API-Key", client_id.header_value().unwrap());
     }
 
-    let client = Client::builder()
-        .user_agent(USER_AGENT.clone())
-        .default_headers(headers)
-        .build()
+    let client = options
+        .build_client(headers)
         .expect("Failed to build HTTP client");
 
     Arc::new(Http::new_with_client(url, client))
 }
 
+/// An ethers client that reports which sub-provider served each call.
+#[async_trait::async_trait]
+pub trait RoutedJsonRpcClient: JsonRpcClient {
+    /// Sends a call, returning its result with the route that served it.
+    async fn request_routed(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> (Route, Result<Value, Self::Error>);
+}
+
+impl BitskiEthersProvider {
+    /// Returns the sub-provider a call to `method` is sent to first. Only [EndpointKind::Bitski]
+    /// networks use the REST route.
+    pub fn route(&self, method: &str) -> Route {
+        match Route::for_method(method) {
+            Route::Authenticated => Route::Authenticated,
+            _ if self.rest_provider.network.endpoint_kind == EndpointKind::Bitski
+                && self.rest_provider.capabilities.supports(method) =>
+            {
+                Route::Rest
+            }
+            _ => Route::Http,
+        }
+    }
+
+    async fn send<T, R>(
+        &self,
+        route: Route,
+        method: &str,
+        params: T,
+    ) -> (Served, Result<R, BitskiError>)
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match route {
+            Route::Authenticated => (
+                Served::new(route),
+                self.authenticated_provider.request(method, params).await,
+            ),
+            Route::Rest => self.rest_provider.request_routed(method, params).await,
+            Route::Http => (
+                Served::new(route),
+                self.http_provider
+                    .request(method, params)
+                    .await
+                    .map_err(BitskiError::from),
+            ),
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl JsonRpcClient for BitskiEthersProvider {
-    type Error = HttpClientError;
+    type Error = BitskiError;
 
     async fn request<T: Debug + Serialize + Send + Sync, R: DeserializeOwned + Send>(
         &self,
         method: &str,
         params: T,
-    ) -> Result<R, HttpClientError> {
-        let result = if AUTH_METHODS.contains(&method) {
-            self.authenticated_provider.request(method, params).await?
-        } else if REST_METHODS.contains(&method) {
-            self.rest_provider.request(method, params).await?
-        } else {
-            self.http_provider.request(method, params).await?
-        };
-
-        Ok(result)
+    ) -> Result<R, BitskiError> {
+        let route = self.route(method);
+        let chain_id = self.rest_provider.network.chain_id;
+
+        observe(
+            method.to_string(),
+            chain_id,
+            self.send(route, method, params),
+        )
+        .await
+    }
+}
+
+#[async_trait::async_trait]
+impl RoutedJsonRpcClient for BitskiEthersProvider {
+    async fn request_routed(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> (Route, Result<Value, BitskiError>) {
+        let (served, result) = self.send(self.route(method), method, params).await;
+        (served.route, result)
     }
 }
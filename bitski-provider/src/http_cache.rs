@@ -0,0 +1,188 @@
+use crate::error::BitskiError;
+use crate::rest::{self, RestResponse};
+use cached::{Cached, SizedCache};
+use reqwest::header::{HeaderMap, HeaderValue, CACHE_CONTROL, ETAG, IF_NONE_MATCH};
+use reqwest::{RequestBuilder, StatusCode};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of responses cached by default.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug)]
+struct Entry {
+    body: Vec<u8>,
+    etag: Option<HeaderValue>,
+    expires: Instant,
+}
+
+/// How a response may be cached, from its `Cache-Control` header.
+#[derive(Debug, PartialEq)]
+struct CachePolicy {
+    store: bool,
+    max_age: Duration,
+}
+
+impl CachePolicy {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let mut policy = CachePolicy {
+            store: true,
+            max_age: Duration::ZERO,
+        };
+        let directives = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for directive in directives {
+            let directive = directive.trim().to_ascii_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => {
+                    if let Ok(seconds) = seconds.trim_matches('"').parse() {
+                        policy.max_age = Duration::from_secs(seconds);
+                    }
+                }
+                _ if directive == "no-store" => policy.store = false,
+                _ if directive == "no-cache" => policy.max_age = Duration::ZERO,
+                _ => {}
+            }
+        }
+        policy
+    }
+}
+
+/// A private HTTP cache for the GET requests of the REST route.
+///
+/// Responses are reused without a request while they are fresh according to their
+/// `Cache-Control: max-age`. Once stale, responses with an `ETag` are revalidated with
+/// `If-None-Match`, and a `304 Not Modified` reuses the cached body. Responses with
+/// `Cache-Control: no-store` are never cached.
+#[derive(Clone, Debug)]
+pub struct HttpCache {
+    entries: Arc<Mutex<SizedCache<String, Entry>>>,
+}
+
+impl Default for HttpCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl HttpCache {
+    /// A cache holding up to `capacity` responses, evicting the least recently used.
+    pub fn new(capacity: usize) -> Self {
+        HttpCache {
+            entries: Arc::new(Mutex::new(SizedCache::with_size(capacity))),
+        }
+    }
+
+    /// Sends a GET request for `url`, answering it from the cache when possible.
+    pub(crate) async fn send(
+        &self,
+        request: RequestBuilder,
+        url: &str,
+    ) -> Result<RestResponse, BitskiError> {
+        let now = Instant::now();
+        let cached = self.entries().cache_get(url).cloned();
+        let request = match &cached {
+            Some(entry) if entry.expires > now => {
+                return Ok(RestResponse::Body(entry.body.clone()))
+            }
+            Some(Entry {
+                etag: Some(etag), ..
+            }) => request.header(IF_NONE_MATCH, etag.clone()),
+            _ => request,
+        };
+
+        let response = request.send().await?;
+        let policy = CachePolicy::from_headers(response.headers());
+        let etag = response.headers().get(ETAG).cloned();
+
+        let (body, etag) = match (response.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(entry)) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("reusing the cached response for {}", url);
+                (entry.body, etag.or(entry.etag))
+            }
+            (status, _) if !status.is_success() => {
+                let error = BitskiError::from_response(&response);
+                let body = response.bytes().await?;
+                return rest::rest_failure(status, error, &body);
+            }
+            _ => (response.bytes().await?.to_vec(), etag),
+        };
+
+        let mut entries = self.entries();
+        match policy.store && (etag.is_some() || !policy.max_age.is_zero()) {
+            true => {
+                entries.cache_set(
+                    url.to_string(),
+                    Entry {
+                        body: body.clone(),
+                        etag,
+                        expires: now + policy.max_age,
+                    },
+                );
+            }
+            false => {
+                entries.cache_remove(url);
+            }
+        }
+        Ok(RestResponse::Body(body))
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, SizedCache<String, Entry>> {
+        self.entries.lock().expect("HTTP cache lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rest_web3_provider::RestWeb3Provider;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use bitski_testkit::TestServer;
+    use serde_json::json;
+    use web3::Transport;
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let block = json!({ "number": "0x10" });
+        let server = TestServer::builder()
+            .with_response("eth_getBlockByNumber", block.clone())
+            .with_response("eth_blockNumber", json!("0x10"))
+            .with_cache_control("eth_getBlockByNumber", "max-age=60")
+            .with_cache_control("eth_blockNumber", "no-cache")
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = RestWeb3Provider::new(network, &"client-id");
+        let params = vec![json!("0x10"), json!(false)];
+
+        for _ in 0..2 {
+            let result = provider
+                .execute("eth_getBlockByNumber", params.clone())
+                .await
+                .unwrap();
+            assert_eq!(result, block);
+            let result = provider.execute("eth_blockNumber", vec![]).await.unwrap();
+            assert_eq!(result, json!("0x10"));
+        }
+
+        // the fresh block is reused, and the block number is revalidated
+        let calls: Vec<_> = server
+            .calls()
+            .into_iter()
+            .map(|call| (call.method, call.not_modified))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("eth_getBlockByNumber".to_string(), false),
+                ("eth_blockNumber".to_string(), false),
+                ("eth_blockNumber".to_string(), true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use crate::USER_AGENT;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, ClientBuilder};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// A function that configures the builder of every HTTP client a provider creates.
+pub type ClientCustomizer = Arc<dyn Fn(ClientBuilder) -> ClientBuilder + Send + Sync>;
+
+/// Options for the HTTP clients used by the authenticated, REST and plain HTTP providers.
+///
+/// Use a customizer to set up proxies, root certificates or client certificates. Extra headers
+/// are sent with every request, but never replace the `X-API-Key`, `Authorization` or
+/// `User-Agent` headers the providers set themselves.
+#[derive(Clone, Default)]
+pub struct HttpClientOptions {
+    pub headers: HeaderMap,
+    pub customizer: Option<ClientCustomizer>,
+}
+
+impl Debug for HttpClientOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpClientOptions")
+            .field("headers", &self.headers)
+            .field("customizer", &self.customizer.is_some())
+            .finish()
+    }
+}
+
+impl HttpClientOptions {
+    /// Adds a header that is sent with every request.
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Sets a function that configures the builder of every HTTP client.
+    pub fn with_customizer<F>(mut self, customizer: F) -> Self
+    where
+        F: Fn(ClientBuilder) -> ClientBuilder + Send + Sync + 'static,
+    {
+        self.customizer = Some(Arc::new(customizer));
+        self
+    }
+
+    /// Returns whether these options leave the clients unchanged.
+    pub fn is_default(&self) -> bool {
+        self.headers.is_empty() && self.customizer.is_none()
+    }
+
+    /// Builds a client that sends the extra headers followed by `headers` and the user agent.
+    pub(crate) fn build_client(&self, headers: HeaderMap) -> reqwest::Result<Client> {
+        let mut default_headers = self.headers.clone();
+        default_headers.extend(headers);
+        let builder = Client::builder()
+            .default_headers(default_headers)
+            .user_agent(USER_AGENT.clone());
+        match &self.customizer {
+            Some(customizer) => customizer(builder),
+            None => builder,
+        }
+        .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpClientOptions;
+    use crate::rest::RestCapabilities;
+    use crate::rest_web3_provider::RestWeb3Provider;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use web3::Transport;
+
+    #[tokio::test]
+    async fn test_extra_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n\"0x1\"")
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&request[..read]).to_lowercase()
+        });
+
+        let customized = Arc::new(AtomicUsize::new(0));
+        let counter = customized.clone();
+        let options = HttpClientOptions::default()
+            .with_header(
+                HeaderName::from_static("x-tenant-id"),
+                HeaderValue::from_static("tenant"),
+            )
+            .with_header(
+                HeaderName::from_static("x-api-key"),
+                HeaderValue::from_static("overridden"),
+            )
+            .with_customizer(move |builder| {
+                counter.fetch_add(1, Ordering::AcqRel);
+                builder
+            });
+        assert!(options.build_client(HeaderMap::new()).is_ok());
+
+        let network =
+            Network::new(format!("http://{}", address), 1).with_endpoint_kind(EndpointKind::Bitski);
+        // a caller-supplied list, so the only request is the call
+        let provider = RestWeb3Provider::new(network, &"client-id")
+            .with_capabilities(RestCapabilities::new(["eth_chainId"]))
+            .with_http_options(options);
+        let result = provider.execute("eth_chainId", vec![]).await.unwrap();
+        assert_eq!(result, serde_json::json!("0x1"));
+
+        let request = server.await.unwrap();
+        assert!(request.contains("x-tenant-id: tenant"));
+        assert!(request.contains("x-api-key: client-id"));
+        assert!(!request.contains("overridden"));
+        assert_eq!(customized.load(Ordering::Acquire), 2);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::error::BitskiError;
+use crate::middleware::Served;
+use std::future::Future;
+use std::time::Instant;
+
+/// Observes a JSON-RPC call, which resolves to its result and the route that served it.
+///
+/// With the `tracing` feature, the call runs in an `rpc_call` span with `method`, `route`,
+/// `chain_id`, `status`, `error_kind`, `retries` and `latency_ms` fields. The route and retries
+/// are recorded once the call has finished, so a REST call that was resent to the JSON-RPC
+/// endpoint is reported as `http` with one retry. With the `metrics` feature, the
+/// `bitski_rpc_requests_total` counter and the `bitski_rpc_request_duration_seconds` histogram
+/// are updated, labelled with the method, route, chain id, status and error kind, and the
+/// `bitski_rpc_retries_total` counter is increased by the retries. The error kind is `none` for
+/// successful calls.
+#[cfg_attr(
+    not(any(feature = "tracing", feature = "metrics")),
+    allow(unused_variables)
+)]
+pub(crate) async fn observe<F, T, E>(method: String, chain_id: u64, call: F) -> Result<T, E>
+where
+    F: Future<Output = (Served, Result<T, E>)>,
+    E: ErrorKind,
+{
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "rpc_call",
+        method = %method,
+        route = tracing::field::Empty,
+        chain_id,
+        status = tracing::field::Empty,
+        error_kind = tracing::field::Empty,
+        retries = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+    );
+    #[cfg(feature = "tracing")]
+    let call = tracing::Instrument::instrument(call, span.clone());
+
+    let start = Instant::now();
+    let (served, result) = call.await;
+    let route = served.route;
+    let latency = start.elapsed();
+    let (status, error_kind) = match &result {
+        Ok(_) => ("ok", "none"),
+        Err(error) => ("error", error.kind()),
+    };
+
+    #[cfg(feature = "tracing")]
+    {
+        span.record("route", route.as_str());
+        span.record("retries", served.retries);
+        span.record("status", status);
+        if result.is_err() {
+            span.record("error_kind", error_kind);
+        }
+        span.record("latency_ms", latency.as_millis() as u64);
+    }
+    #[cfg(feature = "metrics")]
+    {
+        let labels = [
+            ("method", method),
+            ("route", route.as_str().to_string()),
+            ("chain_id", chain_id.to_string()),
+            ("status", status.to_string()),
+            ("error_kind", error_kind.to_string()),
+        ];
+        metrics::counter!("bitski_rpc_requests_total", &labels).increment(1);
+        metrics::histogram!("bitski_rpc_request_duration_seconds", &labels)
+            .record(latency.as_secs_f64());
+        metrics::counter!("bitski_rpc_retries_total", &labels).increment(served.retries.into());
+    }
+
+    result
+}
+
+/// Names the kind of error a call failed with, for the `error_kind` field and label.
+pub(crate) trait ErrorKind {
+    fn kind(&self) -> &'static str;
+}
+
+impl ErrorKind for BitskiError {
+    fn kind(&self) -> &'static str {
+        match self {
+            BitskiError::Transport(_) => "transport",
+            BitskiError::Http { .. } => "http",
+            BitskiError::JsonRpc(_) => "json_rpc",
+            BitskiError::Auth(_) => "auth",
+            BitskiError::Timeout => "timeout",
+            BitskiError::RateLimited { .. } => "rate_limited",
+            BitskiError::Decode(_) => "decode",
+            BitskiError::Config(_) => "config",
+        }
+    }
+}
+
+impl ErrorKind for web3::Error {
+    fn kind(&self) -> &'static str {
+        BitskiError::from(self.clone()).kind()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErrorKind;
+    use crate::error::BitskiError;
+    use std::io;
+
+    #[test]
+    fn test_error_kind() {
+        let timeout = web3::Error::Io(io::ErrorKind::TimedOut.into());
+        assert_eq!(timeout.kind(), "timeout");
+        let rpc = web3::Error::Rpc(jsonrpc_core::Error::method_not_found());
+        assert_eq!(rpc.kind(), "json_rpc");
+        let http = BitskiError::Http {
+            status: 503,
+            body: None,
+        };
+        assert_eq!(http.kind(), "http");
+    }
+}
@@ -2,14 +2,31 @@ pub mod access_token_providers;
 #[cfg(feature = "ethers")]
 pub mod authenticated_ethers_provider;
 pub mod authenticated_web3_provider;
+pub mod cache;
+#[cfg(feature = "testing")]
+pub mod cassette;
+pub mod error;
+pub mod http_cache;
+pub mod http_client;
+mod instrumentation;
+pub mod log_follower;
+pub mod logs;
+pub mod middleware;
+#[cfg(feature = "testing")]
+pub mod mock;
 
 #[cfg(feature = "ethers")]
 pub mod ethers_provider;
+pub mod polling;
 
+pub mod rest;
 #[cfg(feature = "ethers")]
 pub mod rest_ethers_provider;
 pub mod rest_web3_provider;
+pub mod revert;
+pub mod secret;
 pub mod web3_provider;
+pub mod ws_provider;
 
 use once_cell::sync::Lazy;
 
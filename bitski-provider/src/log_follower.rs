@@ -0,0 +1,317 @@
+use crate::logs::{get_logs_in_range, DEFAULT_CONCURRENCY};
+use crate::polling::{block_by_number, confirmed_head, poll_stream, Output, PollingOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use web3::futures::Stream;
+use web3::types::{Filter, Log, H256};
+use web3::{Transport, Web3};
+
+/// A change to the set of logs matching a [LogFollower]'s filter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogEvent {
+    /// The log was included in a block on the canonical chain.
+    Added(Log),
+    /// The log was previously added, but its block was removed by a reorg.
+    Removed(Log),
+}
+
+impl LogEvent {
+    /// Returns the log, with `removed` set if it was removed by a reorg.
+    pub fn into_log(self) -> Log {
+        match self {
+            LogEvent::Added(log) => log,
+            LogEvent::Removed(log) => Log {
+                removed: Some(true),
+                ..log
+            },
+        }
+    }
+}
+
+/// A processed block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockRef {
+    pub number: u64,
+    pub hash: H256,
+}
+
+/// A cursor that can be saved and passed to [LogFollower::resume] to continue following logs
+/// where a previous follower stopped.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The next block to process.
+    pub next_block: u64,
+    /// The most recently processed blocks, oldest first, used to detect reorgs.
+    pub recent_blocks: Vec<BlockRef>,
+}
+
+/// The events of one poll, and the checkpoint to save once they have been handled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogUpdate {
+    pub events: Vec<LogEvent>,
+    pub checkpoint: Checkpoint,
+}
+
+/// Follows the logs matching a filter on confirmed blocks and reports reorgs.
+///
+/// The hashes of the last `reorg_depth` processed blocks are tracked. When the parent hash of the
+/// next block doesn't match the last processed block, the follower walks back to the most recent
+/// block that is still canonical and emits [LogEvent::Removed] for the logs after it, newest
+/// first, before following the new chain.
+#[derive(Debug)]
+pub struct LogFollower<T: Transport> {
+    web3: Web3<T>,
+    filter: Filter,
+    options: PollingOptions,
+    next: Option<u64>,
+    window: VecDeque<BlockRef>,
+    // logs that were added by this follower within the window
+    added: VecDeque<Log>,
+    // the first block whose logs were added by this follower rather than a previous one
+    first_block: Option<u64>,
+    output: VecDeque<LogUpdate>,
+}
+
+impl<T: Transport> LogFollower<T> {
+    /// Follows logs matching `filter`, starting at the current confirmed block. The block range
+    /// of `filter` is ignored.
+    pub fn new(web3: Web3<T>, filter: Filter, options: PollingOptions) -> Self {
+        LogFollower {
+            web3,
+            filter,
+            options,
+            next: None,
+            window: VecDeque::new(),
+            added: VecDeque::new(),
+            first_block: None,
+            output: VecDeque::new(),
+        }
+    }
+
+    /// Follows logs matching `filter`, starting where `checkpoint` was taken.
+    pub fn resume(
+        web3: Web3<T>,
+        filter: Filter,
+        options: PollingOptions,
+        checkpoint: Checkpoint,
+    ) -> Self {
+        LogFollower {
+            next: Some(checkpoint.next_block),
+            window: checkpoint.recent_blocks.into(),
+            first_block: Some(checkpoint.next_block),
+            ..Self::new(web3, filter, options)
+        }
+    }
+
+    /// Returns the current checkpoint, or `None` if nothing has been processed yet.
+    pub fn checkpoint(&self) -> Option<Checkpoint> {
+        Some(Checkpoint {
+            next_block: self.next?,
+            recent_blocks: self.window.iter().copied().collect(),
+        })
+    }
+
+    /// Processes new confirmed blocks, returning `None` if there were none.
+    pub async fn poll(&mut self) -> web3::error::Result<Option<LogUpdate>> {
+        let target = confirmed_head(&self.web3, &self.options).await?;
+        let next = self.next.unwrap_or(target);
+        if next > target {
+            return Ok(None);
+        }
+
+        let Some(first) = block_by_number(&self.web3, next).await? else {
+            return Ok(None);
+        };
+        if let Some(last) = self.window.back() {
+            if last.number + 1 == next && last.hash != first.parent_hash {
+                let events = self.rewind().await?;
+                return Ok(self.update(events));
+            }
+        }
+
+        // track every block within the window, checking that they form a chain
+        let mut blocks: Vec<BlockRef> = Vec::new();
+        let mut parent_hash = first.parent_hash;
+        let tracked = next.max((target + 1).saturating_sub(self.options.reorg_depth));
+        for number in tracked..=target {
+            let block = match number == next {
+                true => Some(first.clone()),
+                false => block_by_number(&self.web3, number).await?,
+            };
+            let Some((hash, block)) = block.and_then(|block| Some((block.hash?, block))) else {
+                return Ok(None);
+            };
+            if number > tracked && block.parent_hash != parent_hash {
+                // a reorg happened while we were fetching, so try again on the next poll
+                return Ok(None);
+            }
+            parent_hash = hash;
+            blocks.push(BlockRef { number, hash });
+        }
+
+        let logs =
+            get_logs_in_range(&self.web3, &self.filter, next, target, DEFAULT_CONCURRENCY).await?;
+        let consistent = logs.iter().all(|log| {
+            let number = log_block(log);
+            blocks
+                .iter()
+                .find(|block| block.number == number)
+                .map_or(true, |block| log.block_hash == Some(block.hash))
+        });
+        if !consistent {
+            return Ok(None);
+        }
+
+        self.window.extend(blocks);
+        while self.window.len() as u64 > self.options.reorg_depth {
+            self.window.pop_front();
+        }
+        self.added.extend(logs.iter().cloned());
+        let oldest = self.window.front().map_or(target, |block| block.number);
+        while matches!(self.added.front(), Some(log) if log_block(log) < oldest) {
+            self.added.pop_front();
+        }
+        self.first_block.get_or_insert(next);
+        self.next = Some(target + 1);
+
+        let events = logs.into_iter().map(LogEvent::Added).collect();
+        Ok(self.update(events))
+    }
+
+    /// Returns a stream of updates, polling for new blocks every `poll_interval`.
+    pub fn into_stream(self) -> impl Stream<Item = web3::error::Result<LogUpdate>> {
+        let interval = self.options.poll_interval;
+        poll_stream(self, interval, |mut follower| async move {
+            let result = match follower.poll().await {
+                Ok(Some(update)) => {
+                    follower.output.push_back(update);
+                    Ok(())
+                }
+                Ok(None) => Ok(()),
+                Err(error) => Err(error),
+            };
+            (follower, result)
+        })
+    }
+
+    fn update(&self, events: Vec<LogEvent>) -> Option<LogUpdate> {
+        Some(LogUpdate {
+            events,
+            checkpoint: self.checkpoint()?,
+        })
+    }
+
+    /// Walks back to the most recent tracked block that is still canonical and removes the logs
+    /// of every block after it.
+    async fn rewind(&mut self) -> web3::error::Result<Vec<LogEvent>> {
+        let mut orphaned = Vec::new();
+        while let Some(block) = self.window.pop_back() {
+            orphaned.push(block);
+            let Some(previous) = self.window.back() else {
+                break;
+            };
+            let canonical = block_by_number(&self.web3, previous.number).await?;
+            if canonical.and_then(|block| block.hash) == Some(previous.hash) {
+                break;
+            }
+        }
+        let fork = orphaned
+            .last()
+            .map_or(0, |block| block.number.saturating_sub(1));
+
+        let mut events = Vec::new();
+        for block in &orphaned {
+            if self
+                .first_block
+                .map_or(false, |first| block.number >= first)
+            {
+                while matches!(self.added.back(), Some(log) if log_block(log) >= block.number) {
+                    let log = self.added.pop_back().expect("checked above");
+                    events.push(LogEvent::Removed(log));
+                }
+            } else {
+                // the block was processed by a previous follower, so ask the node for its logs
+                let mut logs = self.logs_by_hash(block.hash).await?;
+                logs.reverse();
+                events.extend(logs.into_iter().map(LogEvent::Removed));
+            }
+        }
+
+        self.next = Some(fork + 1);
+        Ok(events)
+    }
+
+    async fn logs_by_hash(&self, hash: H256) -> web3::error::Result<Vec<Log>> {
+        let mut filter = serde_json::to_value(&self.filter)?;
+        if let Value::Object(filter) = &mut filter {
+            filter.remove("fromBlock");
+            filter.remove("toBlock");
+            filter.insert("blockHash".to_string(), serde_json::to_value(hash)?);
+        }
+        let logs = self
+            .web3
+            .transport()
+            .execute("eth_getLogs", vec![filter])
+            .await?;
+        Ok(serde_json::from_value(logs)?)
+    }
+}
+
+impl<T: Transport> Output for LogFollower<T> {
+    type Item = LogUpdate;
+
+    fn output(&mut self) -> &mut VecDeque<LogUpdate> {
+        &mut self.output
+    }
+}
+
+fn log_block(log: &Log) -> u64 {
+    log.block_number
+        .map(|number| number.as_u64())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checkpoint, LogEvent, LogFollower};
+    use crate::polling::tests::Chain;
+    use crate::polling::PollingOptions;
+    use web3::types::{FilterBuilder, H256};
+    use web3::Web3;
+
+    #[tokio::test]
+    async fn test_resume_after_reorg() {
+        let chain = Chain::new(&[0xa0, 0xa1, 0xa2]);
+        let filter = FilterBuilder::default().build();
+        let web3 = Web3::new(chain.clone());
+
+        let mut follower =
+            LogFollower::new(web3.clone(), filter.clone(), PollingOptions::default());
+        let update = follower.poll().await.unwrap().unwrap();
+        assert_eq!(update.events.len(), 1);
+        let checkpoint: Checkpoint =
+            serde_json::from_str(&serde_json::to_string(&update.checkpoint).unwrap()).unwrap();
+        assert_eq!(checkpoint.next_block, 3);
+
+        // block 2 is replaced while the follower isn't running
+        chain.replace(2, 0xb2);
+        chain.push(0xb3);
+
+        let mut follower = LogFollower::resume(web3, filter, PollingOptions::default(), checkpoint);
+        let update = follower.poll().await.unwrap().unwrap();
+        assert!(
+            matches!(&update.events[..], [LogEvent::Removed(log)] if log.block_hash == Some(H256::repeat_byte(0xa2)))
+        );
+        assert_eq!(update.checkpoint.next_block, 2);
+
+        let update = follower.poll().await.unwrap().unwrap();
+        let hashes: Vec<_> = update
+            .events
+            .into_iter()
+            .map(|event| event.into_log().block_hash.unwrap())
+            .collect();
+        assert_eq!(hashes, [H256::repeat_byte(0xb2), H256::repeat_byte(0xb3)]);
+        assert_eq!(update.checkpoint.next_block, 4);
+    }
+}
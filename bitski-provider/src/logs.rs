@@ -0,0 +1,160 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, VecDeque};
+use web3::error::TransportError;
+use web3::futures::stream::FuturesUnordered;
+use web3::futures::{FutureExt, StreamExt};
+use web3::types::{BlockNumber, Filter, Log};
+use web3::{Transport, Web3};
+
+/// The number of `eth_getLogs` requests sent at once by default when a range is split.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Messages nodes use to reject `eth_getLogs` requests that cover too many blocks or results.
+static RANGE_ERRORS: &[&str] = &[
+    "query returned more than",
+    "block range",
+    "range is too large",
+    "range too large",
+    "limit exceeded",
+    "response size exceeded",
+    "too many results",
+];
+
+/// Fetches the logs matching `filter` between `from` and `to`, inclusive.
+///
+/// When the node rejects a range as too large, it is split in half and the halves are fetched
+/// again, with at most `max_concurrency` requests in flight. The logs are returned in block
+/// order. The block range of `filter` is ignored.
+pub async fn get_logs_in_range<T: Transport>(
+    web3: &Web3<T>,
+    filter: &Filter,
+    from: u64,
+    to: u64,
+    max_concurrency: usize,
+) -> web3::error::Result<Vec<Log>> {
+    let mut ranges = VecDeque::from([(from, to)]);
+    let mut results = BTreeMap::new();
+    let mut in_flight = FuturesUnordered::new();
+
+    loop {
+        while in_flight.len() < max_concurrency.max(1) {
+            let Some((from, to)) = ranges.pop_front() else {
+                break;
+            };
+            in_flight.push(get_logs(web3, filter, from, to).map(move |result| (from, to, result)));
+        }
+
+        match in_flight.next().await {
+            Some((from, _to, Ok(logs))) => {
+                results.insert(from, logs);
+            }
+            Some((from, to, Err(error))) if from < to && is_range_error(&error) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("Splitting eth_getLogs range {}-{}: {:?}", from, to, error);
+                let middle = from + (to - from) / 2;
+                ranges.push_back((from, middle));
+                ranges.push_back((middle + 1, to));
+            }
+            Some((_from, _to, Err(error))) => return Err(error),
+            None => break,
+        }
+    }
+
+    Ok(results.into_values().flatten().collect())
+}
+
+/// Returns whether `error` means the requested range should be split into smaller ones.
+pub fn is_range_error(error: &web3::error::Error) -> bool {
+    let message = match error {
+        // -32005 is the standard code for a request that exceeds a limit
+        web3::error::Error::Rpc(error) if error.code.code() == -32005 => return true,
+        web3::error::Error::Rpc(error) => &error.message,
+        web3::error::Error::Transport(TransportError::Code(status)) => return *status == 413,
+        web3::error::Error::Transport(TransportError::Message(message)) => message,
+        _ => return false,
+    };
+    let message = message.to_lowercase();
+    RANGE_ERRORS.iter().any(|pattern| message.contains(pattern))
+}
+
+/// Fetches the logs matching `filter` between `from` and `to` with a single request.
+pub(crate) async fn get_logs<T: Transport>(
+    web3: &Web3<T>,
+    filter: &Filter,
+    from: u64,
+    to: u64,
+) -> web3::error::Result<Vec<Log>> {
+    let mut filter = serde_json::to_value(filter)?;
+    filter["fromBlock"] = serde_json::to_value(BlockNumber::Number(from.into()))?;
+    filter["toBlock"] = serde_json::to_value(BlockNumber::Number(to.into()))?;
+    let logs: Value = web3
+        .transport()
+        .execute("eth_getLogs", vec![filter])
+        .await?;
+    Ok(serde_json::from_value(logs)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_logs_in_range;
+    use jsonrpc_core::Call;
+    use serde_json::{json, Value};
+    use web3::futures::future::{self, BoxFuture};
+    use web3::types::FilterBuilder;
+    use web3::{helpers, RequestId, Transport, Web3};
+
+    /// Returns one log per block, and rejects ranges of more than two blocks.
+    #[derive(Clone, Debug)]
+    struct Limited;
+
+    impl Transport for Limited {
+        type Out = BoxFuture<'static, web3::error::Result<Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            (0, helpers::build_request(0, method, params))
+        }
+
+        fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+            let Call::MethodCall(call) = request else {
+                panic!("unexpected request");
+            };
+            let params: Vec<Value> = call.params.parse().unwrap();
+            let number = |value: &Value| {
+                u64::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+            };
+            let from = number(&params[0]["fromBlock"]);
+            let to = number(&params[0]["toBlock"]);
+            let result = if to - from > 1 {
+                Err(web3::error::Error::Rpc(jsonrpc_core::Error {
+                    code: jsonrpc_core::ErrorCode::ServerError(-32602),
+                    message: "query returned more than 10000 results".to_string(),
+                    data: None,
+                }))
+            } else {
+                Ok((from..=to)
+                    .map(|number| {
+                        json!({
+                            "address": "0x0000000000000000000000000000000000000000",
+                            "topics": [],
+                            "data": "0x",
+                            "blockNumber": format!("0x{:x}", number),
+                        })
+                    })
+                    .collect())
+            };
+            Box::pin(future::ready(result))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_range() {
+        let web3 = Web3::new(Limited);
+        let filter = FilterBuilder::default().build();
+        let logs = get_logs_in_range(&web3, &filter, 3, 13, 3).await.unwrap();
+        let numbers: Vec<u64> = logs
+            .into_iter()
+            .map(|log| log.block_number.unwrap().as_u64())
+            .collect();
+        assert_eq!(numbers, (3..=13).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,256 @@
+use crate::rest::DEFAULT_REST_METHODS;
+use crate::web3_provider::{BitskiWeb3Provider, AUTH_METHODS};
+use jsonrpc_core::Call;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use web3::futures::future::BoxFuture;
+use web3::RequestId;
+
+/// The future returned for a call by a [Middleware] and by [Next::run].
+pub type ResponseFuture = BoxFuture<'static, web3::error::Result<Value>>;
+
+/// The sub-provider a call is dispatched to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Route {
+    /// The authenticated JSON-RPC endpoint, for signing and account methods.
+    Authenticated,
+    /// Bitski's REST API, for read methods it serves directly.
+    Rest,
+    /// The plain JSON-RPC endpoint.
+    Http,
+}
+
+impl Route {
+    /// Returns the sub-provider that serves `method` on a Bitski network, with the default REST
+    /// capabilities.
+    pub fn for_method(method: &str) -> Route {
+        if AUTH_METHODS.contains(&method) {
+            Route::Authenticated
+        } else if DEFAULT_REST_METHODS.contains(&method) {
+            Route::Rest
+        } else {
+            Route::Http
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Route::Authenticated => "authenticated",
+            Route::Rest => "rest",
+            Route::Http => "http",
+        }
+    }
+}
+
+/// The sub-provider that served a call, and how many times the call was resent before it was
+/// served, for example on the JSON-RPC route after the REST route couldn't serve it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Served {
+    pub route: Route,
+    pub retries: u32,
+}
+
+impl Served {
+    /// A call served by `route` on the first attempt.
+    pub(crate) fn new(route: Route) -> Self {
+        Served { route, retries: 0 }
+    }
+
+    /// Records that the call is resent to `route`.
+    pub(crate) fn retry(&mut self, route: Route) {
+        self.route = route;
+        self.retries += 1;
+    }
+}
+
+/// Information about a call passed to each [Middleware].
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    pub id: RequestId,
+    /// The sub-provider the call will be dispatched to, chosen from the original request.
+    pub route: Route,
+    pub chain_id: u64,
+    served: Arc<Mutex<Option<Served>>>,
+}
+
+impl RequestContext {
+    pub(crate) fn new(id: RequestId, route: Route, chain_id: u64) -> Self {
+        RequestContext {
+            id,
+            route,
+            chain_id,
+            served: Default::default(),
+        }
+    }
+
+    /// Returns the sub-provider that served the call once its response has arrived, which
+    /// differs from `route` when a REST call falls back to the JSON-RPC endpoint. Until then, or
+    /// if a middleware answered the call itself, returns `route`.
+    pub fn served_route(&self) -> Route {
+        self.served().route
+    }
+
+    /// Returns how many times the call was resent before it was served, once its response has
+    /// arrived.
+    pub fn retries(&self) -> u32 {
+        self.served().retries
+    }
+
+    pub(crate) fn served(&self) -> Served {
+        self.served
+            .lock()
+            .expect("served route lock poisoned")
+            .unwrap_or(Served::new(self.route))
+    }
+
+    pub(crate) fn set_served(&self, served: Served) {
+        *self.served.lock().expect("served route lock poisoned") = Some(served);
+    }
+}
+
+/// Inspects or modifies the calls sent through a [BitskiWeb3Provider] and their responses.
+///
+/// A middleware can rewrite the call before passing it to `next`, map the future `next` returns,
+/// or return a response of its own without calling `next` at all.
+pub trait Middleware: Debug + Send + Sync {
+    fn handle(&self, request: Call, context: &RequestContext, next: Next<'_>) -> ResponseFuture;
+}
+
+/// The rest of the middleware chain, ending with the sub-provider the call is routed to.
+pub struct Next<'a> {
+    pub(crate) middleware: &'a [Arc<dyn Middleware>],
+    pub(crate) provider: &'a BitskiWeb3Provider,
+    pub(crate) context: &'a RequestContext,
+}
+
+impl Next<'_> {
+    /// Passes the call to the next middleware, or dispatches it if there are none left.
+    pub fn run(self, request: Call) -> ResponseFuture {
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => {
+                let next = Next {
+                    middleware: rest,
+                    ..self
+                };
+                middleware.handle(request, self.context, next)
+            }
+            None => self.provider.dispatch(self.context, request),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Middleware, Next, RequestContext, ResponseFuture, Route};
+    use crate::web3_provider::BitskiWeb3Provider;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use bitski_testkit::TestServer;
+    use jsonrpc_core::Call;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use web3::futures::{future, FutureExt};
+    use web3::Transport;
+
+    /// Records the route of every call it sees.
+    #[derive(Debug, Default)]
+    struct Recorder(Arc<Mutex<Vec<Route>>>);
+
+    impl Middleware for Recorder {
+        fn handle(
+            &self,
+            request: Call,
+            context: &RequestContext,
+            next: Next<'_>,
+        ) -> ResponseFuture {
+            self.0.lock().unwrap().push(context.route);
+            next.run(request)
+        }
+    }
+
+    /// Records the route that served every call, and its retries.
+    #[derive(Debug, Default)]
+    struct ServedRecorder(Arc<Mutex<Vec<(Route, u32)>>>);
+
+    impl Middleware for ServedRecorder {
+        fn handle(
+            &self,
+            request: Call,
+            context: &RequestContext,
+            next: Next<'_>,
+        ) -> ResponseFuture {
+            let calls = self.0.clone();
+            let context = context.clone();
+            next.run(request)
+                .map(move |result| {
+                    let served = (context.served_route(), context.retries());
+                    calls.lock().unwrap().push(served);
+                    result
+                })
+                .boxed()
+        }
+    }
+
+    /// Answers every call itself, appending the chain id to the method name.
+    #[derive(Debug)]
+    struct ShortCircuit;
+
+    impl Middleware for ShortCircuit {
+        fn handle(
+            &self,
+            request: Call,
+            context: &RequestContext,
+            _next: Next<'_>,
+        ) -> ResponseFuture {
+            let Call::MethodCall(call) = request else {
+                panic!("unexpected request");
+            };
+            let result = json!(format!("{}:{}", call.method, context.chain_id));
+            future::ready(Ok(result)).boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware_chain() {
+        let network = Network::new("https://api.bitski.com/v1/web3/mainnet", 1);
+        let recorder = Recorder::default();
+        let calls = recorder.0.clone();
+        let provider = BitskiWeb3Provider::new(&network, &"client-id", Arc::new(()))
+            .with_middleware(recorder)
+            .with_middleware(ShortCircuit);
+
+        let result = provider.execute("eth_blockNumber", vec![]).await.unwrap();
+        assert_eq!(result, json!("eth_blockNumber:1"));
+        provider.execute("eth_accounts", vec![]).await.unwrap();
+        provider.execute("eth_call", vec![]).await.unwrap();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [Route::Rest, Route::Authenticated, Route::Http]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_served_route() {
+        let server = TestServer::builder()
+            .with_response("eth_blockNumber", json!("0x10"))
+            .with_rest_methods(Vec::<String>::new())
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let recorder = ServedRecorder::default();
+        let calls = recorder.0.clone();
+        let provider =
+            BitskiWeb3Provider::new(&network, &"client-id", Arc::new(())).with_middleware(recorder);
+
+        provider.execute("eth_blockNumber", vec![]).await.unwrap();
+        provider.execute("eth_blockNumber", vec![]).await.unwrap();
+
+        // the first call is resent to the JSON-RPC endpoint, and later calls are sent there first
+        assert_eq!(*calls.lock().unwrap(), [(Route::Http, 1), (Route::Http, 0)]);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::error::BitskiError;
+use crate::middleware::Route;
+use jsonrpc_core::Call;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use web3::futures::future::{self, BoxFuture};
+use web3::futures::FutureExt;
+use web3::{helpers, BatchTransport, RequestId, Transport};
+
+/// A scripted expectation for a call.
+#[derive(Clone, Debug)]
+struct Expectation {
+    method: String,
+    // `None` matches any params
+    params: Option<Value>,
+    response: Result<Value, BitskiError>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    expectations: VecDeque<Expectation>,
+    failures: HashMap<Route, BitskiError>,
+    calls: Vec<(String, Value)>,
+    unexpected: Vec<String>,
+}
+
+/// A provider for tests that answers calls with scripted responses instead of sending them.
+///
+/// Expectations are matched in the order they were added, so a call that doesn't match the next
+/// expectation fails and is reported by [MockBitskiProvider::verify]. Failures set with
+/// [MockBitskiProvider::fail_route] apply to every call the Bitski provider would send to that
+/// route, for example to simulate an expired access token for signing methods.
+#[derive(Clone, Debug, Default)]
+pub struct MockBitskiProvider {
+    state: Arc<Mutex<State>>,
+    id: Arc<AtomicUsize>,
+}
+
+impl MockBitskiProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects a call to `method` with `params`, answering it with `response`.
+    pub fn expect(&self, method: &str, params: Value, response: Result<Value, BitskiError>) {
+        self.push(method, Some(params), response);
+    }
+
+    /// Expects a call to `method` with any params, answering it with `response`.
+    pub fn expect_any(&self, method: &str, response: Result<Value, BitskiError>) {
+        self.push(method, None, response);
+    }
+
+    /// Fails every call that would be sent to `route` with `error`, before expectations are
+    /// checked.
+    pub fn fail_route(&self, route: Route, error: BitskiError) {
+        self.state().failures.insert(route, error);
+    }
+
+    /// Returns the method and params of every call received, in order.
+    pub fn calls(&self) -> Vec<(String, Value)> {
+        self.state().calls.clone()
+    }
+
+    /// Panics if a call didn't match its expectation, or if any expectation wasn't met.
+    pub fn verify(&self) {
+        let state = self.state();
+        assert!(
+            state.unexpected.is_empty(),
+            "unexpected calls: {:?}",
+            state.unexpected
+        );
+        let remaining: Vec<_> = state
+            .expectations
+            .iter()
+            .map(|expectation| &expectation.method)
+            .collect();
+        assert!(remaining.is_empty(), "expected calls: {:?}", remaining);
+    }
+
+    /// Answers a call to `method` with `params`.
+    pub fn respond(&self, method: &str, params: Value) -> Result<Value, BitskiError> {
+        let mut state = self.state();
+        state.calls.push((method.to_string(), params.clone()));
+
+        if let Some(error) = state.failures.get(&Route::for_method(method)) {
+            return Err(error.clone());
+        }
+
+        let matches = state.expectations.front().map_or(false, |expectation| {
+            expectation.method == method
+                && expectation
+                    .params
+                    .as_ref()
+                    .map_or(true, |expected| *expected == params)
+        });
+        match matches {
+            true => {
+                state
+                    .expectations
+                    .pop_front()
+                    .expect("checked above")
+                    .response
+            }
+            false => {
+                let call = format!("{}({})", method, params);
+                state.unexpected.push(call.clone());
+                Err(BitskiError::Transport(format!("unexpected call {}", call)))
+            }
+        }
+    }
+
+    fn push(&self, method: &str, params: Option<Value>, response: Result<Value, BitskiError>) {
+        self.state().expectations.push_back(Expectation {
+            method: method.to_string(),
+            params,
+            response,
+        });
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("mock state lock poisoned")
+    }
+
+    fn respond_to_call(&self, request: Call) -> web3::error::Result<Value> {
+        let Call::MethodCall(method_call) = request else {
+            return Err(web3::Error::Internal);
+        };
+        let params = serde_json::to_value(&method_call.params)?;
+        Ok(self.respond(&method_call.method, params)?)
+    }
+}
+
+impl Transport for MockBitskiProvider {
+    type Out = BoxFuture<'static, web3::error::Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.id.fetch_add(1, Ordering::AcqRel);
+        let request = helpers::build_request(id, method, params);
+        (id, request)
+    }
+
+    fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+        future::ready(self.respond_to_call(request)).boxed()
+    }
+}
+
+impl BatchTransport for MockBitskiProvider {
+    type Batch = BoxFuture<'static, web3::error::Result<Vec<web3::error::Result<Value>>>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let results = requests
+            .into_iter()
+            .map(|(_id, request)| self.respond_to_call(request))
+            .collect();
+        future::ready(Ok(results)).boxed()
+    }
+}
+
+#[cfg(feature = "ethers")]
+#[async_trait::async_trait]
+impl ethers::prelude::JsonRpcClient for MockBitskiProvider {
+    type Error = BitskiError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, BitskiError>
+    where
+        T: std::fmt::Debug + serde::Serialize + Send + Sync,
+        R: serde::de::DeserializeOwned + Send,
+    {
+        let result = self.respond(method, serde_json::to_value(params)?)?;
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockBitskiProvider;
+    use crate::error::BitskiError;
+    use crate::middleware::Route;
+    use serde_json::json;
+    use web3::Web3;
+
+    #[tokio::test]
+    async fn test_scripted_calls() {
+        let mock = MockBitskiProvider::new();
+        mock.expect("eth_blockNumber", json!([]), Ok(json!("0x10")));
+        mock.expect_any("eth_chainId", Ok(json!("0x1")));
+        mock.fail_route(
+            Route::Authenticated,
+            BitskiError::Auth("expired".to_string()),
+        );
+
+        let web3 = Web3::new(mock.clone());
+        assert_eq!(web3.eth().block_number().await.unwrap(), 16.into());
+        assert_eq!(web3.eth().chain_id().await.unwrap(), 1.into());
+        let error = web3.eth().accounts().await.unwrap_err();
+        assert!(matches!(
+            BitskiError::from(error),
+            BitskiError::Auth(message) if message == "expired"
+        ));
+
+        let methods: Vec<_> = mock.calls().into_iter().map(|(method, _)| method).collect();
+        assert_eq!(methods, ["eth_blockNumber", "eth_chainId", "eth_accounts"]);
+        mock.verify();
+    }
+}
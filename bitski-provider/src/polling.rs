@@ -0,0 +1,370 @@
+use crate::log_follower::LogFollower;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::Duration;
+use web3::futures::{stream, Stream, StreamExt};
+use web3::types::{Block, BlockId, BlockNumber, Filter, Log, H256};
+use web3::{Transport, Web3};
+
+/// Options for the polling block and log streams.
+#[derive(Clone, Debug)]
+pub struct PollingOptions {
+    /// How long to wait between polls for a new block.
+    pub poll_interval: Duration,
+    /// The number of blocks that must be built on top of a block before it is emitted.
+    pub confirmations: u64,
+    /// The number of recent blocks that are tracked to detect reorgs.
+    pub reorg_depth: u64,
+}
+
+impl Default for PollingOptions {
+    fn default() -> Self {
+        PollingOptions {
+            poll_interval: Duration::from_secs(5),
+            confirmations: 0,
+            reorg_depth: 64,
+        }
+    }
+}
+
+/// Returns a stream of new blocks, found by polling `eth_blockNumber`.
+///
+/// The stream starts at the current confirmed block. When a reorg replaces blocks that were
+/// already emitted, the replacements are emitted again. Errors are yielded without ending the
+/// stream, and polling resumes after the next interval.
+pub fn block_stream<T: Transport>(
+    web3: Web3<T>,
+    options: PollingOptions,
+) -> impl Stream<Item = web3::error::Result<Block<H256>>> {
+    let interval = options.poll_interval;
+    let poller = BlockPoller {
+        web3,
+        options,
+        next: None,
+        window: VecDeque::new(),
+        output: VecDeque::new(),
+    };
+    poll_stream(poller, interval, |mut poller| async move {
+        let result = poller.poll().await;
+        (poller, result)
+    })
+}
+
+/// Returns a stream of logs matching `filter` in new blocks, found by polling `eth_blockNumber`
+/// and `eth_getLogs`.
+///
+/// The block range of `filter` is ignored; the stream starts at the current confirmed block.
+/// When a reorg removes blocks whose logs were already emitted, those logs are emitted again
+/// with `removed` set, followed by the logs of the replacement blocks. Use a [LogFollower]
+/// directly to be able to save and resume progress.
+pub fn log_stream<T: Transport>(
+    web3: Web3<T>,
+    filter: Filter,
+    options: PollingOptions,
+) -> impl Stream<Item = web3::error::Result<Log>> {
+    LogFollower::new(web3, filter, options)
+        .into_stream()
+        .map(|update| {
+            let logs: Vec<_> = match update {
+                Ok(update) => update
+                    .events
+                    .into_iter()
+                    .map(|event| Ok(event.into_log()))
+                    .collect(),
+                Err(error) => vec![Err(error)],
+            };
+            stream::iter(logs)
+        })
+        .flatten()
+}
+
+pub(crate) trait Output {
+    type Item;
+
+    fn output(&mut self) -> &mut VecDeque<Self::Item>;
+}
+
+/// Turns a poller into a stream that drains its output and polls again after `interval`.
+pub(crate) fn poll_stream<P, F, Fut>(
+    poller: P,
+    interval: Duration,
+    poll: F,
+) -> impl Stream<Item = web3::error::Result<P::Item>>
+where
+    P: Output,
+    F: FnMut(P) -> Fut,
+    Fut: Future<Output = (P, web3::error::Result<()>)>,
+{
+    stream::unfold(
+        (poller, poll, true),
+        move |(mut poller, mut poll, first)| async move {
+            if let Some(item) = poller.output().pop_front() {
+                return Some((Ok(item), (poller, poll, first)));
+            }
+            if !first {
+                tokio::time::sleep(interval).await;
+            }
+            loop {
+                let (mut next, result) = poll(poller).await;
+                if let Err(error) = result {
+                    return Some((Err(error), (next, poll, false)));
+                }
+                if let Some(item) = next.output().pop_front() {
+                    return Some((Ok(item), (next, poll, false)));
+                }
+                tokio::time::sleep(interval).await;
+                poller = next;
+            }
+        },
+    )
+}
+
+pub(crate) async fn block_by_number<T: Transport>(
+    web3: &Web3<T>,
+    number: u64,
+) -> web3::error::Result<Option<Block<H256>>> {
+    web3.eth()
+        .block(BlockId::Number(BlockNumber::Number(number.into())))
+        .await
+}
+
+pub(crate) async fn confirmed_head<T: Transport>(
+    web3: &Web3<T>,
+    options: &PollingOptions,
+) -> web3::error::Result<u64> {
+    let head = web3.eth().block_number().await?.as_u64();
+    Ok(head.saturating_sub(options.confirmations))
+}
+
+struct BlockPoller<T: Transport> {
+    web3: Web3<T>,
+    options: PollingOptions,
+    next: Option<u64>,
+    // the numbers and hashes of the most recently emitted blocks
+    window: VecDeque<(u64, H256)>,
+    output: VecDeque<Block<H256>>,
+}
+
+impl<T: Transport> Output for BlockPoller<T> {
+    type Item = Block<H256>;
+
+    fn output(&mut self) -> &mut VecDeque<Block<H256>> {
+        &mut self.output
+    }
+}
+
+impl<T: Transport> BlockPoller<T> {
+    async fn poll(&mut self) -> web3::error::Result<()> {
+        let target = confirmed_head(&self.web3, &self.options).await?;
+        let mut next = self.next.unwrap_or(target);
+
+        while next <= target {
+            let Some(block) = block_by_number(&self.web3, next).await? else {
+                break;
+            };
+            let Some(hash) = block.hash else {
+                break;
+            };
+
+            if let Some(&(number, parent_hash)) = self.window.back() {
+                if number + 1 == next && parent_hash != block.parent_hash {
+                    // the previous block was replaced, so step back and emit its replacement
+                    self.window.pop_back();
+                    next -= 1;
+                    self.next = Some(next);
+                    continue;
+                }
+            }
+
+            self.window.push_back((next, hash));
+            while self.window.len() as u64 > self.options.reorg_depth {
+                self.window.pop_front();
+            }
+            self.output.push_back(block);
+            next += 1;
+            self.next = Some(next);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{block_stream, log_stream, PollingOptions};
+    use jsonrpc_core::Call;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use web3::futures::future::{self, BoxFuture};
+    use web3::futures::StreamExt;
+    use web3::types::{FilterBuilder, H256};
+    use web3::{helpers, RequestId, Transport, Web3};
+
+    #[derive(Debug, Default)]
+    struct Blocks {
+        // the hashes of the canonical blocks, indexed by number
+        canonical: Vec<H256>,
+        // the numbers of every block that was ever created, including orphaned ones
+        numbers: HashMap<H256, usize>,
+    }
+
+    /// A chain where every block has a single log, and blocks are identified by a byte that is
+    /// repeated to form their hash.
+    #[derive(Clone, Debug, Default)]
+    pub(crate) struct Chain(Arc<Mutex<Blocks>>);
+
+    impl Chain {
+        pub(crate) fn new(blocks: &[u8]) -> Self {
+            let chain = Chain::default();
+            for byte in blocks {
+                chain.push(*byte);
+            }
+            chain
+        }
+
+        pub(crate) fn push(&self, byte: u8) {
+            let number = self.0.lock().unwrap().canonical.len();
+            self.replace(number, byte);
+        }
+
+        /// Replaces the block at `number`, dropping every block after it.
+        pub(crate) fn replace(&self, number: usize, byte: u8) {
+            let mut blocks = self.0.lock().unwrap();
+            let hash = H256::repeat_byte(byte);
+            blocks.canonical.truncate(number);
+            blocks.canonical.push(hash);
+            blocks.numbers.insert(hash, number);
+        }
+
+        fn block(&self, number: usize) -> Value {
+            let blocks = self.0.lock().unwrap();
+            let parent_hash = match number {
+                0 => H256::zero(),
+                number => blocks.canonical[number - 1],
+            };
+            block(number, blocks.canonical[number], parent_hash)
+        }
+
+        fn log(&self, hash: H256) -> Value {
+            let number = self.0.lock().unwrap().numbers[&hash];
+            json!({
+                "address": "0x0000000000000000000000000000000000000000",
+                "topics": [],
+                "data": "0x",
+                "blockHash": hash,
+                "blockNumber": format!("0x{:x}", number),
+            })
+        }
+    }
+
+    fn block(number: usize, hash: H256, parent_hash: H256) -> Value {
+        json!({
+            "hash": hash,
+            "parentHash": parent_hash,
+            "number": format!("0x{:x}", number),
+            "sha3Uncles": H256::zero(),
+            "miner": "0x0000000000000000000000000000000000000000",
+            "stateRoot": H256::zero(),
+            "transactionsRoot": H256::zero(),
+            "receiptsRoot": H256::zero(),
+            "gasUsed": "0x0",
+            "gasLimit": "0x0",
+            "extraData": "0x",
+            "timestamp": "0x0",
+            "difficulty": "0x0",
+            "uncles": [],
+            "transactions": [],
+        })
+    }
+
+    impl Transport for Chain {
+        type Out = BoxFuture<'static, web3::error::Result<Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+            (0, helpers::build_request(0, method, params))
+        }
+
+        fn send(&self, _id: RequestId, request: Call) -> Self::Out {
+            let Call::MethodCall(call) = request else {
+                panic!("unexpected request");
+            };
+            let params: Vec<Value> = call.params.parse().unwrap();
+            let number = |value: &Value| {
+                usize::from_str_radix(value.as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+            };
+            let head = self.0.lock().unwrap().canonical.len() - 1;
+            let result = match call.method.as_str() {
+                "eth_blockNumber" => json!(format!("0x{:x}", head)),
+                "eth_getBlockByNumber" => self.block(number(&params[0])),
+                "eth_getLogs" if params[0].get("blockHash").is_some() => {
+                    let hash = serde_json::from_value(params[0]["blockHash"].clone()).unwrap();
+                    json!([self.log(hash)])
+                }
+                "eth_getLogs" => {
+                    let from = number(&params[0]["fromBlock"]);
+                    let to = number(&params[0]["toBlock"]);
+                    let canonical = self.0.lock().unwrap().canonical.clone();
+                    (from..=to)
+                        .map(|number| self.log(canonical[number]))
+                        .collect()
+                }
+                method => panic!("unexpected method {}", method),
+            };
+            Box::pin(future::ready(Ok(result)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_block_stream_reorg() {
+        let chain = Chain::new(&[0xa0, 0xa1, 0xa2]);
+        let options = PollingOptions {
+            poll_interval: Duration::from_millis(1),
+            ..PollingOptions::default()
+        };
+        let mut blocks = Box::pin(block_stream(Web3::new(chain.clone()), options));
+
+        let block = blocks.next().await.unwrap().unwrap();
+        assert_eq!(block.hash, Some(H256::repeat_byte(0xa2)));
+
+        // replace block 2 and build block 3 on top of the replacement
+        chain.replace(2, 0xb2);
+        chain.push(0xb3);
+
+        // the stream starts again from the fork point
+        for hash in [H256::repeat_byte(0xb2), H256::repeat_byte(0xb3)] {
+            let block = blocks.next().await.unwrap().unwrap();
+            assert_eq!(block.hash, Some(hash));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_stream_reorg() {
+        let chain = Chain::new(&[0xa0, 0xa1, 0xa2]);
+        let options = PollingOptions {
+            poll_interval: Duration::from_millis(1),
+            ..PollingOptions::default()
+        };
+        let filter = FilterBuilder::default().build();
+        let mut logs = Box::pin(log_stream(Web3::new(chain.clone()), filter, options));
+
+        let log = logs.next().await.unwrap().unwrap();
+        assert_eq!(log.block_hash, Some(H256::repeat_byte(0xa2)));
+        assert_eq!(log.removed, None);
+
+        // replace block 2 and build block 3 on top of the replacement
+        chain.replace(2, 0xb2);
+        chain.push(0xb3);
+
+        let log = logs.next().await.unwrap().unwrap();
+        assert_eq!(log.block_hash, Some(H256::repeat_byte(0xa2)));
+        assert_eq!(log.removed, Some(true));
+
+        for hash in [H256::repeat_byte(0xb2), H256::repeat_byte(0xb3)] {
+            let log = logs.next().await.unwrap().unwrap();
+            assert_eq!(log.block_hash, Some(hash));
+            assert_eq!(log.removed, None);
+        }
+    }
+}
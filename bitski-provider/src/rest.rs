@@ -0,0 +1,395 @@
+use crate::error::{BitskiError, JsonRpcError};
+use crate::secret::Secret;
+use bitski_chain_models::networks::{EndpointKind, Network};
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use tokio::sync::OnceCell;
+
+/// Cacheable read methods served by the Bitski REST API.
+pub static DEFAULT_REST_METHODS: &[&str] = &[
+    "eth_blockNumber",
+    "eth_getBlockByNumber",
+    "eth_getBlockByHash",
+    "eth_getTransactionByHash",
+    "eth_getTransactionReceipt",
+    "eth_getBalance",
+    "eth_getCode",
+    "eth_getLogs",
+    "eth_chainId",
+    "net_version",
+];
+
+/// The longest URL sent to the REST route by default. Longer requests, such as `eth_getLogs`
+/// filters with many addresses or topics, are sent to the JSON-RPC route instead, since many
+/// servers and proxies reject long URLs.
+pub const DEFAULT_MAX_URL_LENGTH: usize = 2048;
+
+/// The methods a REST provider sends to the REST route.
+///
+/// The default capabilities are discovered: before its first REST call, a provider reads the
+/// methods the endpoint serves from `GET {rpc_url}/capabilities`, which answers with
+/// `{"methods": [...]}`. Until then, or if the endpoint doesn't answer with a list, the
+/// [DEFAULT_REST_METHODS] are used. A list passed to [RestCapabilities::new] is used as it is.
+///
+/// When the REST route answers that it doesn't serve a method, with `405 Method Not Allowed`,
+/// `501 Not Implemented`, or `404 Not Found` and a JSON-RPC "method not found" error, the call is
+/// retried on the JSON-RPC route and the method is removed, so later calls go to the JSON-RPC
+/// route directly. Any other `404 Not Found` means the block, transaction or other resource
+/// doesn't exist, and the call returns `null`. Clones share the same list.
+#[derive(Clone, Debug)]
+pub struct RestCapabilities {
+    methods: Arc<RwLock<HashSet<String>>>,
+    // set for capabilities that are read from the endpoint, once they have been read
+    discovered: Option<Arc<OnceCell<()>>>,
+}
+
+impl Default for RestCapabilities {
+    fn default() -> Self {
+        RestCapabilities {
+            discovered: Some(Default::default()),
+            ..Self::new(DEFAULT_REST_METHODS.iter().copied())
+        }
+    }
+}
+
+/// The body of a capability list response.
+#[derive(Deserialize)]
+struct CapabilityList {
+    methods: Vec<String>,
+}
+
+impl RestCapabilities {
+    pub fn new<I, S>(methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        RestCapabilities {
+            methods: Arc::new(RwLock::new(
+                methods
+                    .into_iter()
+                    .map(|method| method.to_string())
+                    .collect(),
+            )),
+            discovered: None,
+        }
+    }
+
+    /// Reads the methods the REST route of `network` serves, the first time it is called for
+    /// discovered capabilities. Keeps the current methods if the list can't be read.
+    pub(crate) async fn discover(
+        &self,
+        client: &reqwest::Client,
+        network: &Network,
+        client_id: &Secret,
+    ) {
+        let Some(discovered) = &self.discovered else {
+            return;
+        };
+        discovered
+            .get_or_init(|| async {
+                match fetch_capabilities(client, network, client_id).await {
+                    Ok(methods) => {
+                        *self.methods.write().expect("capabilities lock poisoned") =
+                            methods.into_iter().collect();
+                    }
+                    Err(_error) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("using the default REST methods: {}", _error);
+                    }
+                }
+            })
+            .await;
+    }
+
+    /// Returns whether calls to `method` are sent to the REST route.
+    pub fn supports(&self, method: &str) -> bool {
+        self.methods
+            .read()
+            .expect("capabilities lock poisoned")
+            .contains(method)
+    }
+
+    /// Sends later calls to `method` to the JSON-RPC route.
+    pub fn remove(&self, method: &str) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!("{} is not supported by the REST route", method);
+        self.methods
+            .write()
+            .expect("capabilities lock poisoned")
+            .remove(method);
+    }
+
+    /// Returns the methods sent to the REST route.
+    pub fn methods(&self) -> Vec<String> {
+        let mut methods: Vec<_> = self
+            .methods
+            .read()
+            .expect("capabilities lock poisoned")
+            .iter()
+            .cloned()
+            .collect();
+        methods.sort();
+        methods
+    }
+}
+
+/// Fetches the list of methods the REST route of `network` serves.
+async fn fetch_capabilities(
+    client: &reqwest::Client,
+    network: &Network,
+    client_id: &Secret,
+) -> Result<Vec<String>, BitskiError> {
+    let url = format!("{}/capabilities", network.rpc_url);
+    let api_key = client_id
+        .header_value()
+        .map_err(|_error| BitskiError::Config("invalid API key".to_string()))?;
+    let response = client.get(url).header("X-API-Key", api_key).send().await?;
+    if !response.status().is_success() {
+        let error = BitskiError::from_response(&response);
+        return Err(error.with_body(&response.bytes().await?));
+    }
+    let list: CapabilityList = serde_json::from_slice(&response.bytes().await?)?;
+    Ok(list.methods)
+}
+
+/// The JSON-RPC code for a method the server doesn't serve.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The outcome of a call to the REST route.
+pub(crate) enum RestResponse {
+    /// The body of a successful response.
+    Body(Vec<u8>),
+    /// The REST route doesn't serve the method.
+    Unsupported,
+    /// `404 Not Found` for this call only, such as for a missing resource, which is answered
+    /// with `null`.
+    NotFound,
+}
+
+/// The body of a REST response that failed with a JSON-RPC error.
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    error: JsonRpcError,
+}
+
+#[derive(Deserialize)]
+struct JsonRpcResponse {
+    #[serde(default)]
+    result: Value,
+    error: Option<JsonRpcError>,
+}
+
+/// Returns the REST URL for a call, with the params percent-encoded, or `None` if the URL would
+/// be longer than `max_url_length`.
+pub(crate) fn rest_url(
+    rpc_url: &str,
+    method: &str,
+    params: &str,
+    max_url_length: usize,
+) -> Result<Option<Url>, BitskiError> {
+    let mut url: Url = format!("{}/{}", rpc_url, method)
+        .parse()
+        .map_err(|error| BitskiError::Config(format!("invalid RPC URL: {}", error)))?;
+    url.query_pairs_mut().append_pair("params", params);
+    Ok((url.as_str().len() <= max_url_length).then_some(url))
+}
+
+/// Classifies an unsuccessful REST response: calls the REST route can't serve are retried on the
+/// JSON-RPC route, calls for missing resources return `null`, and any other failure is returned as
+/// an error.
+pub(crate) fn rest_failure(
+    status: StatusCode,
+    status_error: BitskiError,
+    body: &[u8],
+) -> Result<RestResponse, BitskiError> {
+    let method_not_found = || {
+        serde_json::from_slice::<ErrorEnvelope>(body)
+            .map_or(false, |envelope| envelope.error.code == METHOD_NOT_FOUND)
+    };
+    match status {
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => {
+            Ok(RestResponse::Unsupported)
+        }
+        StatusCode::NOT_FOUND if method_not_found() => Ok(RestResponse::Unsupported),
+        StatusCode::NOT_FOUND => Ok(RestResponse::NotFound),
+        _ => Err(rest_error(status_error, body)),
+    }
+}
+
+/// Returns the error for an unsuccessful REST response, using the JSON-RPC error in its body if
+/// it has one, or `status_error` with the start of the body otherwise.
+pub(crate) fn rest_error(status_error: BitskiError, body: &[u8]) -> BitskiError {
+    match serde_json::from_slice::<ErrorEnvelope>(body) {
+        Ok(envelope) => BitskiError::JsonRpc(envelope.error),
+        Err(_) => status_error.with_body(body),
+    }
+}
+
+/// Decodes the body of a successful REST response. A JSON-RPC error in the body is returned as an
+/// error, and decode errors include the body for debugging.
+#[cfg(feature = "ethers")]
+pub(crate) fn decode_body<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, BitskiError> {
+    if let Ok(envelope) = serde_json::from_slice::<ErrorEnvelope>(body) {
+        return Err(BitskiError::JsonRpc(envelope.error));
+    }
+    serde_json::from_slice(body).map_err(|error| {
+        BitskiError::Decode(format!("{}: {}", error, String::from_utf8_lossy(body)))
+    })
+}
+
+/// Sends a call to the JSON-RPC route of `network`, for calls the REST route can't serve. The API
+/// key is only sent to [EndpointKind::Bitski] networks.
+pub(crate) async fn send_json_rpc(
+    client: &reqwest::Client,
+    network: &Network,
+    client_id: &Secret,
+    id: Value,
+    method: &str,
+    params: Value,
+) -> Result<Value, BitskiError> {
+    let body = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!("sending {} to the JSON-RPC route", method);
+    let mut request = client.post(&network.rpc_url).json(&body);
+    if network.endpoint_kind == EndpointKind::Bitski {
+        let api_key = client_id
+            .header_value()
+            .map_err(|_error| BitskiError::Config("invalid API key".to_string()))?;
+        request = request.header("X-API-Key", api_key);
+    }
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let error = BitskiError::from_response(&response);
+        return Err(rest_error(error, &response.bytes().await?));
+    }
+
+    let response: JsonRpcResponse = serde_json::from_slice(&response.bytes().await?)?;
+    match response.error {
+        Some(error) => Err(BitskiError::JsonRpc(error)),
+        None => Ok(response.result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestCapabilities;
+    use crate::rest_web3_provider::RestWeb3Provider;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use bitski_testkit::TestServer;
+    use serde_json::json;
+    use web3::Transport;
+
+    #[tokio::test]
+    async fn test_long_requests_use_json_rpc() {
+        let logs = json!([{ "data": "0x" }]);
+        let server = TestServer::builder()
+            .with_response("eth_getLogs", logs.clone())
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = RestWeb3Provider::new(network, &"client-id").with_max_url_length(256);
+
+        let short = json!({ "topics": ["0x01"], "blockHash": "0x02 &#?" });
+        let addresses = vec![format!("0x{:040x}", 1); 10];
+        let long = json!({ "address": addresses });
+        for filter in [&short, &long] {
+            let result = provider
+                .execute("eth_getLogs", vec![filter.clone()])
+                .await
+                .unwrap();
+            assert_eq!(result, logs);
+        }
+
+        let calls = server.calls();
+        assert!(calls[0].rest);
+        assert_eq!(calls[0].params, json!([short]));
+        assert!(!calls[1].rest);
+        assert_eq!(calls[1].params, json!([long]));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_methods_use_json_rpc() {
+        let server = TestServer::builder()
+            .with_response("eth_getCode", json!("0x"))
+            .with_rest_methods(["eth_blockNumber"])
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = RestWeb3Provider::new(network, &"client-id");
+        assert!(provider.capabilities.supports("eth_getCode"));
+
+        let params = vec![json!(format!("0x{:040x}", 1)), json!("latest")];
+        for _ in 0..2 {
+            let result = provider.execute("eth_getCode", params.clone()).await;
+            assert_eq!(result.unwrap(), json!("0x"));
+        }
+        assert!(!provider.capabilities.supports("eth_getCode"));
+
+        // the REST route is only tried once
+        let routes: Vec<_> = server.calls().into_iter().map(|call| call.rest).collect();
+        assert_eq!(routes, [true, false, false]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_resources_are_null() {
+        let server = TestServer::builder()
+            .with_response("eth_getTransactionByHash", json!(null))
+            .with_rest_not_found("eth_getTransactionByHash")
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = RestWeb3Provider::new(network, &"client-id");
+
+        let params = vec![json!(format!("0x{:064x}", 1))];
+        for _ in 0..2 {
+            let result = provider
+                .execute("eth_getTransactionByHash", params.clone())
+                .await;
+            assert_eq!(result.unwrap(), json!(null));
+        }
+        assert!(provider.capabilities.supports("eth_getTransactionByHash"));
+
+        // every call is answered by the REST route, without a JSON-RPC retry
+        let routes: Vec<_> = server.calls().into_iter().map(|call| call.rest).collect();
+        assert_eq!(routes, [true, true]);
+    }
+
+    #[tokio::test]
+    async fn test_discovered_capabilities() {
+        let server = TestServer::builder()
+            .with_response("eth_blockNumber", json!("0x10"))
+            .with_response("eth_chainId", json!("0x1"))
+            .with_rest_capabilities(["eth_chainId"])
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = RestWeb3Provider::new(network.clone(), &"client-id");
+        assert!(provider.capabilities.supports("eth_blockNumber"));
+
+        provider.execute("eth_blockNumber", vec![]).await.unwrap();
+        provider.execute("eth_chainId", vec![]).await.unwrap();
+        assert!(!provider.capabilities.supports("eth_blockNumber"));
+        assert!(provider.capabilities.supports("eth_chainId"));
+
+        // a caller-supplied list is used as it is
+        let capabilities = RestCapabilities::new(["eth_blockNumber"]);
+        let provider = RestWeb3Provider::new(network, &"client-id").with_capabilities(capabilities);
+        provider.execute("eth_blockNumber", vec![]).await.unwrap();
+
+        let routes: Vec<_> = server.calls().into_iter().map(|call| call.rest).collect();
+        assert_eq!(routes, [false, true, true]);
+    }
+}
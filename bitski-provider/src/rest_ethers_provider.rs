@@ -1,16 +1,24 @@
+use crate::error::BitskiError;
+use crate::http_client::HttpClientOptions;
+use crate::middleware::{Route, Served};
+use crate::rest::{self, RestCapabilities, RestResponse, DEFAULT_MAX_URL_LENGTH};
+use crate::secret::Secret;
 use crate::USER_AGENT;
 use bitski_chain_models::networks::Network;
-use ethers::prelude::{HttpClientError, JsonRpcClient};
+use ethers::prelude::JsonRpcClient;
 use once_cell::sync::Lazy;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
 use std::fmt::Debug;
 
 #[derive(Debug, Clone)]
 pub struct RestEthersProvider {
     client: reqwest::Client,
     pub network: Network,
-    client_id: String,
+    client_id: Secret,
+    max_url_length: usize,
+    pub capabilities: RestCapabilities,
 }
 
 static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
@@ -25,48 +33,203 @@ impl RestEthersProvider {
         RestEthersProvider {
             client: CLIENT.clone(),
             network,
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
+            max_url_length: DEFAULT_MAX_URL_LENGTH,
+            capabilities: RestCapabilities::default(),
         }
     }
 
+    /// Sends calls whose REST URL would be longer than `max_url_length` to the JSON-RPC route.
+    pub fn with_max_url_length(mut self, max_url_length: usize) -> Self {
+        self.max_url_length = max_url_length;
+        self
+    }
+
+    /// Sends the methods in `capabilities` to the REST route, and every other method to the
+    /// JSON-RPC route.
+    pub fn with_capabilities(mut self, capabilities: RestCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Uses a client built with `options` instead of the shared default client.
+    pub fn with_http_options(mut self, options: HttpClientOptions) -> Self {
+        self.client = options
+            .build_client(Default::default())
+            .expect("could not build REST client");
+        self
+    }
+
+    /// Sends a call, returning the result with the route that served it: [Route::Http] if it
+    /// was sent to the JSON-RPC endpoint, and [Route::Rest] otherwise.
+    pub(crate) async fn request_routed<I: Debug + Serialize, T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: I,
+    ) -> (Served, Result<T, BitskiError>) {
+        let mut served = Served::new(Route::Rest);
+        let result = self.send(method, params, &mut served).await;
+        (served, result)
+    }
+
     async fn send<I: Debug + Serialize, T: DeserializeOwned>(
         &self,
         method: &str,
         params: I,
-    ) -> Result<T, HttpClientError> {
-        let url = format!(
-            "{}/{}?params={}",
-            self.network.rpc_url,
-            method,
-            serde_json::to_string(&params).map_err(|error| HttpClientError::SerdeJson {
-                err: error,
-                text: format!("{params:?}")
-            })?,
-        );
+        served: &mut Served,
+    ) -> Result<T, BitskiError> {
+        self.capabilities
+            .discover(&self.client, &self.network, &self.client_id)
+            .await;
+        let url = match self.capabilities.supports(method) {
+            true => rest::rest_url(
+                &self.network.rpc_url,
+                method,
+                &serde_json::to_string(&params)?,
+                self.max_url_length,
+            )?,
+            false => None,
+        };
+        let Some(url) = url else {
+            *served = Served::new(Route::Http);
+            return self.send_json_rpc(method, params).await;
+        };
 
         let response = self
             .client
             .get(url)
-            .header("X-API-Key", &self.client_id)
+            .header(
+                "X-API-Key",
+                self.client_id
+                    .header_value()
+                    .map_err(|_error| BitskiError::Config("invalid API key".to_string()))?,
+            )
             .send()
-            .await?
-            .json()
             .await?;
+        let status = response.status();
+        let failure = match status.is_success() {
+            true => None,
+            false => Some(BitskiError::from_response(&response)),
+        };
+        let body = response.bytes().await?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            "received response to {}: {:?}",
+            method,
+            String::from_utf8_lossy(&body)
+        );
+        let response = match failure {
+            Some(failure) => rest::rest_failure(status, failure, &body)?,
+            None => RestResponse::Body(body.to_vec()),
+        };
+
+        match response {
+            RestResponse::Body(body) => rest::decode_body(&body),
+            RestResponse::Unsupported => {
+                self.capabilities.remove(method);
+                served.retry(Route::Http);
+                self.send_json_rpc(method, params).await
+            }
+            RestResponse::NotFound => Ok(serde_json::from_value(Value::Null)?),
+        }
+    }
 
-        Ok(response)
+    /// Sends a call to the JSON-RPC route, for calls the REST route can't serve.
+    async fn send_json_rpc<I: Serialize, T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: I,
+    ) -> Result<T, BitskiError> {
+        let result = rest::send_json_rpc(
+            &self.client,
+            &self.network,
+            &self.client_id,
+            1.into(),
+            method,
+            serde_json::to_value(&params)?,
+        )
+        .await?;
+        Ok(serde_json::from_value(result)?)
     }
 }
 
 #[async_trait::async_trait]
 impl JsonRpcClient for RestEthersProvider {
-    type Error = HttpClientError;
+    type Error = BitskiError;
 
     async fn request<T: Debug + Serialize + Send + Sync, R: DeserializeOwned + Send>(
         &self,
         method: &str,
         params: T,
-    ) -> Result<R, HttpClientError> {
-        let output = self.send(method, params).await?;
-        Ok(output)
+    ) -> Result<R, BitskiError> {
+        let (_served, output) = self.request_routed(method, params).await;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestEthersProvider;
+    use crate::error::BitskiError;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use bitski_testkit::TestServer;
+    use ethers::prelude::JsonRpcClient;
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn test_error_responses() {
+        let server = TestServer::builder()
+            .with_response("eth_blockNumber", json!("0x10"))
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = RestEthersProvider::new(network, &"client-id");
+
+        let result: Value = provider.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(result, json!("0x10"));
+
+        // the server answers unknown methods with a JSON-RPC error and a 400 status
+        let error = provider
+            .request::<_, Value>("eth_getCode", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, BitskiError::JsonRpc(error) if error.code == -32601));
+
+        // decode errors include the body
+        let error = provider
+            .request::<_, u64>("eth_blockNumber", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, BitskiError::Decode(message) if message.contains("0x10")));
+    }
+
+    #[tokio::test]
+    async fn test_error_body_is_kept() {
+        let page = format!("<html>502 Bad Gateway{}</html>", " ".repeat(2000));
+        let server = TestServer::builder()
+            .with_rest_failure("eth_blockNumber", 502, &page)
+            .start()
+            .await
+            .unwrap();
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let provider = RestEthersProvider::new(network, &"client-id");
+
+        let error = provider
+            .request::<_, Value>("eth_blockNumber", ())
+            .await
+            .unwrap_err();
+        let BitskiError::Http {
+            status: 502,
+            body: Some(body),
+        } = error
+        else {
+            panic!("unexpected error {:?}", error);
+        };
+        // the body is truncated
+        assert!(body.starts_with("<html>502 Bad Gateway"));
+        assert_eq!(body.len(), 1024);
     }
 }
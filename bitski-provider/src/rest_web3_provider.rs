@@ -1,3 +1,9 @@
+use crate::error::BitskiError;
+use crate::http_cache::HttpCache;
+use crate::http_client::HttpClientOptions;
+use crate::middleware::{Route, Served};
+use crate::rest::{self, RestCapabilities, RestResponse, DEFAULT_MAX_URL_LENGTH};
+use crate::secret::Secret;
 use crate::USER_AGENT;
 use bitski_chain_models::networks::Network;
 use jsonrpc_core::Call;
@@ -5,7 +11,6 @@ use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use web3::error::TransportError;
 use web3::futures::future::BoxFuture;
 use web3::{helpers, Error, RequestId, Transport};
 
@@ -15,7 +20,10 @@ use once_cell::sync::Lazy;
 pub struct RestWeb3Provider {
     client: reqwest::Client,
     pub network: Network,
-    client_id: String,
+    client_id: Secret,
+    max_url_length: usize,
+    http_cache: HttpCache,
+    pub capabilities: RestCapabilities,
     id: Arc<AtomicUsize>,
 }
 
@@ -31,16 +39,54 @@ impl RestWeb3Provider {
         RestWeb3Provider {
             client: CLIENT.clone(),
             network,
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
+            max_url_length: DEFAULT_MAX_URL_LENGTH,
+            http_cache: HttpCache::default(),
+            capabilities: RestCapabilities::default(),
             id: Arc::new(AtomicUsize::new(0)),
         }
     }
 
+    /// Sends calls whose REST URL would be longer than `max_url_length` to the JSON-RPC route.
+    pub fn with_max_url_length(mut self, max_url_length: usize) -> Self {
+        self.max_url_length = max_url_length;
+        self
+    }
+
+    /// Sends the methods in `capabilities` to the REST route, and every other method to the
+    /// JSON-RPC route.
+    pub fn with_capabilities(mut self, capabilities: RestCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Uses `http_cache` for GET requests, for example to share a cache between providers. By
+    /// default each provider has its own [HttpCache].
+    pub fn with_http_cache(mut self, http_cache: HttpCache) -> Self {
+        self.http_cache = http_cache;
+        self
+    }
+
+    /// Uses a client built with `options` instead of the shared default client.
+    pub fn with_http_options(mut self, options: HttpClientOptions) -> Self {
+        self.client = options
+            .build_client(Default::default())
+            .expect("could not build REST client");
+        self
+    }
+
+    /// Sends a call, returning the result with the route that served it: [Route::Http] if it
+    /// was sent to the JSON-RPC endpoint, and [Route::Rest] otherwise.
+    pub(crate) async fn send_routed(self, request: Call) -> (Served, web3::error::Result<Value>) {
+        let mut served = Served::new(Route::Rest);
+        let result = self.send(request, &mut served).await;
+        (served, result)
+    }
+
     async fn send<T: DeserializeOwned>(
-        client: &reqwest::Client,
+        self,
         request: Call,
-        network: Network,
-        client_id: String,
+        served: &mut Served,
     ) -> web3::error::Result<T> {
         let method_call = match request {
             Call::MethodCall(ref method_call) => method_call,
@@ -49,53 +95,69 @@ impl RestWeb3Provider {
         #[cfg(feature = "tracing")]
         let request_id = serde_json::to_string(&method_call.id).unwrap_or_default();
 
-        let url = format!(
-            "{}/{}?params={}",
-            network.rpc_url,
-            method_call.method,
-            serde_json::to_string(&method_call.params)?,
-        );
+        self.capabilities
+            .discover(&self.client, &self.network, &self.client_id)
+            .await;
+        let url = match self.capabilities.supports(&method_call.method) {
+            true => rest::rest_url(
+                &self.network.rpc_url,
+                &method_call.method,
+                &serde_json::to_string(&method_call.params)?,
+                self.max_url_length,
+            )?,
+            false => None,
+        };
+        let response = match url {
+            Some(url) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    "[id:{}] sending request: {:?} to {}",
+                    request_id,
+                    serde_json::to_string(&request)?,
+                    url
+                );
+                let request = self.client.get(url.clone()).header(
+                    "X-API-Key",
+                    self.client_id
+                        .header_value()
+                        .map_err(|_error| BitskiError::Config("invalid API key".to_string()))?,
+                );
+                match self.http_cache.send(request, url.as_str()).await? {
+                    RestResponse::Body(body) => Some(body),
+                    RestResponse::Unsupported => {
+                        self.capabilities.remove(&method_call.method);
+                        served.retry(Route::Http);
+                        None
+                    }
+                    RestResponse::NotFound => return Ok(serde_json::from_value(Value::Null)?),
+                }
+            }
+            None => {
+                *served = Served::new(Route::Http);
+                None
+            }
+        };
 
-        #[cfg(feature = "tracing")]
-        tracing::debug!(
-            "[id:{}] sending request: {:?} to {}",
-            request_id,
-            serde_json::to_string(&request)?,
-            url
-        );
-        let response = client
-            .get(url)
-            .header("X-API-Key", client_id)
-            .send()
-            .await
-            .map_err(|err| {
-                Error::Transport(TransportError::Message(format!(
-                    "failed to send request: {}",
-                    err
-                )))
-            })?;
-        let status = response.status();
-        let response = response.bytes().await.map_err(|err| {
-            Error::Transport(TransportError::Message(format!(
-                "failed to read response bytes: {}",
-                err
-            )))
-        })?;
+        let Some(response) = response else {
+            let result = rest::send_json_rpc(
+                &self.client,
+                &self.network,
+                &self.client_id,
+                serde_json::to_value(&method_call.id)?,
+                &method_call.method,
+                serde_json::to_value(&method_call.params)?,
+            )
+            .await?;
+            return Ok(serde_json::from_value(result)?);
+        };
         #[cfg(feature = "tracing")]
         tracing::debug!(
             "[id:{}] received response: {:?}",
             request_id,
             String::from_utf8_lossy(&response)
         );
-        if !status.is_success() {
-            return Err(Error::Transport(TransportError::Code(status.as_u16())));
-        }
-        helpers::arbitrary_precision_deserialize_workaround(&response).map_err(|err| {
-            Error::Transport(TransportError::Message(format!(
-                "failed to deserialize response: {}",
-                err
-            )))
-        })
+        helpers::arbitrary_precision_deserialize_workaround(&response)
+            .map_err(|err| BitskiError::from(err).into())
     }
 }
 
@@ -109,12 +171,10 @@ impl Transport for RestWeb3Provider {
     }
 
     fn send(&self, _id: RequestId, call: Call) -> Self::Out {
-        let client = self.client.clone();
-        let network = self.network.clone();
-        let client_id = self.client_id.clone();
+        let provider = self.clone();
         Box::pin(async move {
-            let output: jsonrpc_core::Value = Self::send(&client, call, network, client_id).await?;
-            Ok(output)
+            let (_served, output) = provider.send_routed(call).await;
+            output
         })
     }
 }
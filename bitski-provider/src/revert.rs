@@ -0,0 +1,204 @@
+use crate::error::BitskiError;
+use serde_json::Value;
+use std::fmt::{self, Display, Write};
+use web3::ethabi::{self, AbiError, ParamType, Token};
+use web3::types::{Bytes, U256};
+
+/// The selector of `Error(string)`, used by `revert("...")` and `require(condition, "...")`.
+const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// The selector of `Panic(uint256)`, used by failed assertions and arithmetic errors.
+const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// The reason a call reverted, decoded from its revert data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RevertReason {
+    /// The message of an `Error(string)`.
+    Error(String),
+    /// The code of a `Panic(uint256)`.
+    Panic(U256),
+    /// A custom error from one of the ABIs passed to the decoder.
+    Custom { name: String, params: Vec<Token> },
+    /// Revert data that didn't match any known error.
+    Unknown(Vec<u8>),
+}
+
+impl RevertReason {
+    /// Decodes revert data, trying `errors` for custom errors. Returns `None` if there is no
+    /// revert data.
+    pub fn decode(data: &[u8], errors: &[AbiError]) -> Option<Self> {
+        if data.is_empty() {
+            return None;
+        }
+        let (selector, params) = data.split_at(data.len().min(4));
+
+        let reason = match selector {
+            selector if selector == ERROR_SELECTOR => ethabi::decode(&[ParamType::String], params)
+                .ok()
+                .and_then(|tokens| tokens.into_iter().next()?.into_string())
+                .map(RevertReason::Error),
+            selector if selector == PANIC_SELECTOR => {
+                ethabi::decode(&[ParamType::Uint(256)], params)
+                    .ok()
+                    .and_then(|tokens| tokens.into_iter().next()?.into_uint())
+                    .map(RevertReason::Panic)
+            }
+            selector => errors
+                .iter()
+                .filter(|error| error.signature()[..4] == *selector)
+                .find_map(|error| {
+                    let params = error.decode(params).ok()?;
+                    Some(RevertReason::Custom {
+                        name: error.name.clone(),
+                        params,
+                    })
+                }),
+        };
+        Some(reason.unwrap_or_else(|| RevertReason::Unknown(data.to_vec())))
+    }
+
+    /// Decodes the `data` of a JSON-RPC error, which nodes return either as a hex string or as
+    /// an object with a nested `data` field.
+    pub fn from_error_data(data: &Value, errors: &[AbiError]) -> Option<Self> {
+        match data {
+            Value::String(_) => {
+                let bytes: Bytes = serde_json::from_value(data.clone()).ok()?;
+                Self::decode(&bytes.0, errors)
+            }
+            Value::Object(object) => Self::from_error_data(object.get("data")?, errors),
+            _ => None,
+        }
+    }
+
+    /// Decodes the revert data of a web3 error.
+    pub fn from_web3_error(error: &web3::Error, errors: &[AbiError]) -> Option<Self> {
+        match error {
+            web3::Error::Rpc(error) => Self::from_error_data(error.data.as_ref()?, errors),
+            _ => None,
+        }
+    }
+
+    /// Decodes the revert data of an ethers provider error.
+    #[cfg(feature = "ethers")]
+    pub fn from_ethers_error(
+        error: &dyn ethers::providers::RpcError,
+        errors: &[AbiError],
+    ) -> Option<Self> {
+        let data = error.as_error_response()?.data.as_ref()?;
+        Self::from_error_data(data, errors)
+    }
+
+    /// Returns a description of a panic code, as documented by Solidity.
+    pub fn panic_description(code: U256) -> Option<&'static str> {
+        let description = match code.low_u64() {
+            _ if code > U256::from(u64::MAX) => return None,
+            0x00 => "generic compiler panic",
+            0x01 => "assertion failed",
+            0x11 => "arithmetic overflow or underflow",
+            0x12 => "division or modulo by zero",
+            0x21 => "invalid enum value",
+            0x22 => "invalid storage byte array encoding",
+            0x31 => "pop on an empty array",
+            0x32 => "array index out of bounds",
+            0x41 => "out of memory",
+            0x51 => "call to an uninitialized function",
+            _ => return None,
+        };
+        Some(description)
+    }
+}
+
+impl BitskiError {
+    /// Decodes the revert data of a JSON-RPC error, trying `errors` for custom errors.
+    pub fn revert_reason(&self, errors: &[AbiError]) -> Option<RevertReason> {
+        RevertReason::from_error_data(self.as_json_rpc()?.data.as_ref()?, errors)
+    }
+}
+
+impl Display for RevertReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertReason::Error(message) => write!(f, "reverted: {}", message),
+            RevertReason::Panic(code) => match Self::panic_description(*code) {
+                Some(description) => write!(f, "panicked: {} (0x{:x})", description, code),
+                None => write!(f, "panicked: 0x{:x}", code),
+            },
+            RevertReason::Custom { name, params } => {
+                let params: Vec<_> = params.iter().map(ToString::to_string).collect();
+                write!(f, "reverted: {}({})", name, params.join(", "))
+            }
+            RevertReason::Unknown(data) => {
+                write!(f, "reverted with unknown data: 0x{}", encode_hex(data))
+            }
+        }
+    }
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RevertReason;
+    use crate::error::{BitskiError, JsonRpcError};
+    use serde_json::json;
+    use web3::ethabi::{AbiError, Param, ParamType, Token};
+    use web3::types::U256;
+
+    #[test]
+    fn test_decode_revert_reasons() {
+        // require(false, "Not owner")
+        let error = BitskiError::JsonRpc(JsonRpcError {
+            code: 3,
+            message: "execution reverted: Not owner".to_string(),
+            data: Some(json!(
+                "0x08c379a0\
+                 0000000000000000000000000000000000000000000000000000000000000020\
+                 0000000000000000000000000000000000000000000000000000000000000009\
+                 4e6f74206f776e65720000000000000000000000000000000000000000000000"
+            )),
+        });
+        let reason = error.revert_reason(&[]).unwrap();
+        assert_eq!(reason, RevertReason::Error("Not owner".to_string()));
+        assert_eq!(reason.to_string(), "reverted: Not owner");
+
+        let panic = json!({
+            "message": "execution reverted",
+            "data": "0x4e487b710000000000000000000000000000000000000000000000000000000000000011",
+        });
+        let reason = RevertReason::from_error_data(&panic, &[]).unwrap();
+        assert_eq!(reason, RevertReason::Panic(U256::from(0x11)));
+        assert_eq!(
+            reason.to_string(),
+            "panicked: arithmetic overflow or underflow (0x11)"
+        );
+
+        let insufficient_balance = AbiError {
+            name: "InsufficientBalance".to_string(),
+            inputs: vec![Param {
+                name: "available".to_string(),
+                kind: ParamType::Uint(256),
+                internal_type: None,
+            }],
+        };
+        let data = insufficient_balance
+            .encode(&[Token::Uint(7.into())])
+            .unwrap();
+        let reason = RevertReason::decode(&data, &[insufficient_balance.clone()]).unwrap();
+        assert_eq!(
+            reason,
+            RevertReason::Custom {
+                name: "InsufficientBalance".to_string(),
+                params: vec![Token::Uint(7.into())],
+            }
+        );
+        assert!(matches!(
+            RevertReason::decode(&data, &[]),
+            Some(RevertReason::Unknown(_))
+        ));
+    }
+}
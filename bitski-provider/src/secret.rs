@@ -0,0 +1,77 @@
+use reqwest::header::{HeaderValue, InvalidHeaderValue};
+use std::fmt::{self, Debug};
+
+/// A value such as an API key or access token that must not appear in logs.
+///
+/// `Debug` prints `Secret([redacted])`, and there is no `Display` implementation, so the value
+/// can only be read with [Secret::expose].
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    /// Returns the secret value.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns a header value that is marked as sensitive, so HTTP clients don't log it.
+    pub(crate) fn header_value(&self) -> Result<HeaderValue, InvalidHeaderValue> {
+        sensitive_header(&self.0)
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Secret(value.to_string())
+    }
+}
+
+/// Returns a header value that is marked as sensitive, such as an `Authorization` header.
+pub(crate) fn sensitive_header(value: &str) -> Result<HeaderValue, InvalidHeaderValue> {
+    let mut header = HeaderValue::from_str(value)?;
+    header.set_sensitive(true);
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Secret;
+    use crate::access_token_providers::ClientCredentialsAccessTokenProvider;
+    use crate::web3_provider::BitskiWeb3Provider;
+    use bitski_chain_models::networks::Network;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let network = Network::new("https://api.bitski.com/v1/web3/mainnet", 1);
+        let token = Arc::new(Secret::from("access-token"));
+        let provider = BitskiWeb3Provider::new(&network, &"key-1234", token);
+        let output = format!("{:?}", provider);
+        assert!(!output.contains("access-token"));
+        assert!(!output.contains("key-1234"));
+        assert_eq!(provider.client_id.expose(), "key-1234");
+
+        let credentials = ClientCredentialsAccessTokenProvider::new(
+            "credential-id".to_string(),
+            "client-secret".to_string(),
+            None,
+        );
+        assert!(!format!("{:?}", credentials).contains("client-secret"));
+    }
+}
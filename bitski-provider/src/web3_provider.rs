@@ -1,20 +1,26 @@
 use crate::access_token_providers::AccessTokenProvider;
 use crate::authenticated_web3_provider::AuthenticatedWeb3Provider;
+use crate::http_client::HttpClientOptions;
+use crate::instrumentation::observe;
+use crate::middleware::{Middleware, Next, RequestContext, ResponseFuture, Route, Served};
+use crate::rest::RestCapabilities;
 use crate::rest_web3_provider::RestWeb3Provider;
-use crate::USER_AGENT;
-use bitski_chain_models::networks::Network;
+use crate::secret::Secret;
+use bitski_chain_models::networks::{EndpointKind, Network};
 use cached::proc_macro::cached;
 use jsonrpc_core::futures::future::BoxFuture;
 use jsonrpc_core::Call;
-use reqwest::header::HeaderValue;
-use reqwest::{header, Client, Url};
+use reqwest::{header, Url};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use web3::futures::future::Shared;
 use web3::futures::FutureExt;
 use web3::transports::Http;
 use web3::{helpers, BatchTransport, RequestId, Transport};
 
-static AUTH_METHODS: &[&str] = &[
+pub(crate) static AUTH_METHODS: &[&str] = &[
     "eth_sendTransaction",
     "eth_accounts",
     "eth_sign",
@@ -24,20 +30,78 @@ static AUTH_METHODS: &[&str] = &[
     "eth_signTypedData_v4",
 ];
 
-static REST_METHODS: &[&str] = &[
-    "eth_blockNumber",
-    "eth_getBlockByNumber",
-    "net_version",
-    "eth_getLogs",
-];
-
 #[derive(Clone, Debug)]
 pub struct BitskiWeb3Provider {
-    pub client_id: String,
+    pub client_id: Secret,
     pub authenticated_provider: Arc<AuthenticatedWeb3Provider>,
     pub rest_provider: Arc<RestWeb3Provider>,
     pub http_provider: Arc<Http>,
     id: Arc<AtomicUsize>,
+    deduplicator: Arc<Deduplicator>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+/// The response to a call, with the sub-provider that served it.
+type RoutedResponse = (Served, web3::error::Result<jsonrpc_core::Value>);
+
+type SharedResponse = Shared<BoxFuture<'static, RoutedResponse>>;
+
+/// Coalesces identical concurrent calls so they share a single upstream request.
+#[derive(Default)]
+struct Deduplicator {
+    methods: HashSet<String>,
+    in_flight: Arc<Mutex<HashMap<String, SharedResponse>>>,
+}
+
+impl Debug for Deduplicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Deduplicator")
+            .field("methods", &self.methods)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Deduplicator {
+    /// Returns the key identical calls share, if calls to this method are deduplicated.
+    fn key(&self, request: &Call) -> Option<String> {
+        match request {
+            Call::MethodCall(method_call) if self.methods.contains(&method_call.method) => {
+                Some(format!(
+                    "{}:{}",
+                    method_call.method,
+                    serde_json::to_string(&method_call.params).unwrap_or_default()
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the in-flight response for `key`, or starts a new one with `send`.
+    fn send<F>(&self, key: String, send: F) -> BoxFuture<'static, RoutedResponse>
+    where
+        F: FnOnce() -> BoxFuture<'static, RoutedResponse>,
+    {
+        let mut in_flight = self.in_flight.lock().expect("in-flight lock poisoned");
+        if let Some(response) = in_flight.get(&key) {
+            return response.clone().boxed();
+        }
+
+        let response = send();
+        let requests = self.in_flight.clone();
+        let cleanup_key = key.clone();
+        let shared = async move {
+            let result = response.await;
+            requests
+                .lock()
+                .expect("in-flight lock poisoned")
+                .remove(&cleanup_key);
+            result
+        }
+        .boxed()
+        .shared();
+        in_flight.insert(key, shared.clone());
+        shared.boxed()
+    }
 }
 
 impl BitskiWeb3Provider {
@@ -47,33 +111,143 @@ impl BitskiWeb3Provider {
         auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
     ) -> Self {
         BitskiWeb3Provider {
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
             authenticated_provider: Arc::new(AuthenticatedWeb3Provider::new(
                 network.clone(),
                 client_id,
                 auth_token_provider,
             )),
             rest_provider: Arc::new(RestWeb3Provider::new(network.clone(), client_id)),
-            http_provider: http_provider(network.clone(), client_id.to_string()),
+            http_provider: http_provider(network.clone(), Secret::new(client_id.to_string())),
             id: Arc::new(AtomicUsize::new(0)),
+            deduplicator: Arc::new(Deduplicator::default()),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Adds a middleware that every call passes through before it is dispatched. Middleware runs
+    /// in the order it was added. Batches are sent as they are.
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Sends calls to `methods` to the REST route instead of the default REST methods.
+    pub fn with_rest_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.rest_provider = Arc::new(
+            RestWeb3Provider::clone(&self.rest_provider)
+                .with_capabilities(RestCapabilities::new(methods)),
+        );
+        self
+    }
+
+    /// Builds the HTTP clients of every sub-provider with `options`.
+    pub fn with_http_options(mut self, options: HttpClientOptions) -> Self {
+        let network = self.rest_provider.network.clone();
+        self.authenticated_provider = Arc::new(
+            AuthenticatedWeb3Provider::clone(&self.authenticated_provider)
+                .with_http_options(options.clone()),
+        );
+        self.rest_provider = Arc::new(
+            RestWeb3Provider::clone(&self.rest_provider).with_http_options(options.clone()),
+        );
+        self.http_provider = build_http_provider(&network, &self.client_id, &options);
+        self
+    }
+
+    /// Coalesces identical concurrent calls to `methods`, so that calls with the same method and
+    /// params share a single upstream request and all receive its result.
+    pub fn with_deduplicated_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.deduplicator = Arc::new(Deduplicator {
+            methods: methods
+                .into_iter()
+                .map(|method| method.to_string())
+                .collect(),
+            in_flight: Default::default(),
+        });
+        self
+    }
+
+    /// Returns the sub-provider a call is sent to. Only [EndpointKind::Bitski] networks use the
+    /// REST route.
+    pub fn route(&self, request: &Call) -> Route {
+        let Call::MethodCall(method_call) = request else {
+            return Route::Http;
+        };
+        let method = &method_call.method;
+        match Route::for_method(method) {
+            Route::Authenticated => Route::Authenticated,
+            _ if self.rest_provider.network.endpoint_kind == EndpointKind::Bitski
+                && self.rest_provider.capabilities.supports(method) =>
+            {
+                Route::Rest
+            }
+            _ => Route::Http,
+        }
+    }
+
+    /// Sends a call that has been through the middleware chain to its sub-provider.
+    pub(crate) fn dispatch(&self, context: &RequestContext, request: Call) -> ResponseFuture {
+        let id = context.id;
+        let route = context.route;
+        let key = self.deduplicator.key(&request);
+        let send = move || match route {
+            Route::Authenticated => self
+                .authenticated_provider
+                .send(id, request)
+                .map(move |result| (Served::new(route), result))
+                .boxed(),
+            Route::Rest => RestWeb3Provider::clone(&self.rest_provider)
+                .send_routed(request)
+                .boxed(),
+            Route::Http => self
+                .http_provider
+                .send(id, request)
+                .map(move |result| (Served::new(route), result))
+                .boxed(),
+        };
+        let response = match key {
+            Some(key) => self.deduplicator.send(key, send),
+            None => send(),
+        };
+        let context = context.clone();
+        async move {
+            let (served, result) = response.await;
+            context.set_served(served);
+            result
         }
+        .boxed()
     }
 }
 
 #[cached]
-fn http_provider(network: Network, client_id: String) -> Arc<Http> {
+fn http_provider(network: Network, client_id: Secret) -> Arc<Http> {
+    build_http_provider(&network, &client_id, &HttpClientOptions::default())
+}
+
+fn build_http_provider(
+    network: &Network,
+    client_id: &Secret,
+    options: &HttpClientOptions,
+) -> Arc<Http> {
     let url: Url = network.rpc_url.parse().expect("Failed to parse RPC URL");
 
     let mut headers = header::HeaderMap::new();
 
-    if url.as_str().contains("api.bitski.com") {
-        headers.insert("X-API-Key", HeaderValue::from_str(&client_id).unwrap());
+    if network.endpoint_kind == EndpointKind::Bitski {
+        headers.insert("X-API-Key", client_id.header_value().unwrap());
     }
 
-    let client = Client::builder()
-        .user_agent(USER_AGENT.clone())
-        .default_headers(headers)
-        .build()
+    let client = options
+        .build_client(headers)
         .expect("Failed to build HTTP client");
 
     Arc::new(Http::with_client(client, url))
@@ -89,20 +263,28 @@ impl Transport for BitskiWeb3Provider {
     }
 
     fn send(&self, id: RequestId, request: Call) -> Self::Out {
-        match &request {
-            Call::MethodCall(method_call)
-                if AUTH_METHODS.contains(&method_call.method.as_str()) =>
-            {
-                self.authenticated_provider.send(id, request).boxed()
-            }
-            Call::MethodCall(method_call)
-                if REST_METHODS.contains(&method_call.method.as_str())
-                    && self.rest_provider.network.rpc_url.contains("bitski.com") =>
-            {
-                self.rest_provider.send(id, request).boxed()
-            }
-            _ => self.http_provider.send(id, request).boxed(),
-        }
+        let context = RequestContext::new(
+            id,
+            self.route(&request),
+            self.rest_provider.network.chain_id,
+        );
+        let method = match &request {
+            Call::MethodCall(method_call) => method_call.method.clone(),
+            Call::Notification(notification) => notification.method.clone(),
+            Call::Invalid { .. } => "invalid".to_string(),
+        };
+        let next = Next {
+            middleware: &self.middleware,
+            provider: self,
+            context: &context,
+        };
+        let response = next.run(request);
+        let chain_id = context.chain_id;
+        observe(method, chain_id, async move {
+            let result = response.await;
+            (context.served(), result)
+        })
+        .boxed()
     }
 }
 
@@ -117,3 +299,87 @@ impl BatchTransport for BitskiWeb3Provider {
         self.authenticated_provider.send_batch(requests)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Deduplicator;
+    use crate::middleware::{Route, Served};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use web3::futures::future::join_all;
+    use web3::futures::FutureExt;
+
+    #[tokio::test]
+    async fn test_deduplicate_concurrent_calls() {
+        let deduplicator = Deduplicator::default();
+        let sent = Arc::new(AtomicUsize::new(0));
+
+        let responses: Vec<_> = (0..3)
+            .map(|_| {
+                let sent = sent.clone();
+                deduplicator.send("eth_blockNumber:[]".to_string(), move || {
+                    sent.fetch_add(1, Ordering::AcqRel);
+                    async {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        (Served::new(Route::Rest), Ok(json!("0x10")))
+                    }
+                    .boxed()
+                })
+            })
+            .collect();
+        for (served, response) in join_all(responses).await {
+            assert_eq!(served.route, Route::Rest);
+            assert_eq!(response.unwrap(), json!("0x10"));
+        }
+        assert_eq!(sent.load(Ordering::Acquire), 1);
+
+        // once the call has finished, the next one is sent upstream again
+        let response = deduplicator.send("eth_blockNumber:[]".to_string(), || {
+            async { (Served::new(Route::Http), Ok(json!("0x11"))) }.boxed()
+        });
+        let (served, response) = response.await;
+        assert_eq!(served.route, Route::Http);
+        assert_eq!(response.unwrap(), json!("0x11"));
+    }
+
+    #[tokio::test]
+    async fn test_api_key_only_sent_to_bitski() {
+        use super::BitskiWeb3Provider;
+        use bitski_chain_models::networks::{EndpointKind, Network};
+        use bitski_testkit::TestServer;
+        use web3::Transport;
+
+        let server = TestServer::builder()
+            .with_response("eth_blockNumber", json!("0x10"))
+            .start()
+            .await
+            .unwrap();
+        let token = Arc::new(server.access_token().to_string());
+        for endpoint_kind in [EndpointKind::JsonRpc, EndpointKind::Bitski] {
+            let network =
+                Network::new(server.rpc_url("mainnet"), 1337).with_endpoint_kind(endpoint_kind);
+            let provider = BitskiWeb3Provider::new(&network, &"client-id", token.clone());
+            provider.execute("eth_accounts", vec![]).await.unwrap();
+            provider.execute("eth_blockNumber", vec![]).await.unwrap();
+
+            #[cfg(feature = "ethers")]
+            {
+                use crate::ethers_provider::BitskiEthersProvider;
+                use ethers::prelude::JsonRpcClient;
+
+                let provider = BitskiEthersProvider::new(&network, &"client-id", token.clone());
+                let _: Vec<String> = provider.request("eth_accounts", ()).await.unwrap();
+                let _: String = provider.request("eth_blockNumber", ()).await.unwrap();
+            }
+        }
+
+        let calls = server.calls();
+        let (json_rpc, bitski) = calls.split_at(calls.len() / 2);
+        assert!(json_rpc.iter().all(|call| call.api_key.is_none()));
+        assert!(bitski
+            .iter()
+            .all(|call| call.api_key.as_deref() == Some("client-id")));
+    }
+}
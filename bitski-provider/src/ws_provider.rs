@@ -0,0 +1,1071 @@
+use crate::access_token_providers::AccessTokenProvider;
+use crate::http_client::HttpClientOptions;
+use crate::secret::{sensitive_header, Secret};
+use crate::USER_AGENT;
+use bitski_chain_models::networks::{EndpointKind, Network};
+use jsonrpc_core::Call;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderMap, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use web3::error::TransportError;
+use web3::futures::channel::{mpsc, oneshot};
+use web3::futures::future::{join_all, BoxFuture};
+use web3::futures::{FutureExt, SinkExt, StreamExt};
+use web3::{api, helpers, BatchTransport, DuplexTransport, RequestId, Transport};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A WebSocket transport for Bitski supporting `eth_subscribe`.
+///
+/// The connection is driven by a background task on the current tokio runtime, which is shut
+/// down once every clone of the transport has been dropped. When the connection drops, the task
+/// reconnects according to its [ReconnectPolicy], re-sends requests that were awaiting a
+/// response, and re-issues every active subscription so existing notification streams keep
+/// working. Requests that are unsafe to send twice, such as `eth_sendRawTransaction`, fail
+/// instead of being re-sent.
+///
+/// Notifications that arrive before the caller starts listening to a subscription are kept,
+/// up to [MAX_BACKLOG] per subscription; older ones are dropped.
+#[derive(Clone, Debug)]
+pub struct BitskiWs {
+    pub network: Network,
+    instructions: mpsc::UnboundedSender<Instruction>,
+    id: Arc<AtomicUsize>,
+}
+
+/// Controls how [BitskiWs] reconnects after the connection drops.
+#[derive(Clone, Debug)]
+pub struct ReconnectPolicy {
+    /// The delay before the first reconnection attempt, doubled after every failed attempt.
+    pub initial_backoff: Duration,
+    /// The maximum delay between reconnection attempts.
+    pub max_backoff: Duration,
+    /// The number of consecutive failed attempts after which the transport gives up, or `None`
+    /// to retry forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_attempts: None,
+        }
+    }
+}
+
+/// Emitted after [BitskiWs] has reconnected, so callers can backfill notifications they missed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Gap {
+    /// The highest block number received from a `newHeads` subscription before the connection
+    /// dropped, if any.
+    pub last_block: Option<u64>,
+    /// How long the transport was disconnected.
+    pub downtime: Duration,
+}
+
+/// The number of notifications kept for a subscription nobody is listening to yet.
+pub const MAX_BACKLOG: usize = 1024;
+
+/// Methods that are not re-sent after a reconnect, since the node may have already processed them.
+const NON_IDEMPOTENT_METHODS: &[&str] = &["eth_sendRawTransaction", "eth_sendTransaction"];
+
+#[derive(Debug)]
+enum Failure {
+    Rpc(jsonrpc_core::Error),
+    Closed,
+    // the connection dropped before a response to a request that is unsafe to re-send
+    Interrupted,
+}
+
+#[derive(Debug)]
+enum Instruction {
+    Request {
+        id: RequestId,
+        request: String,
+        // the params of an `eth_subscribe` request
+        subscribe: Option<Value>,
+        sender: oneshot::Sender<Result<Value, Failure>>,
+    },
+    Subscribe {
+        id: SubscriptionKey,
+        sink: mpsc::UnboundedSender<Value>,
+    },
+    Unsubscribe {
+        id: SubscriptionKey,
+    },
+    Gaps {
+        sink: mpsc::UnboundedSender<Gap>,
+    },
+}
+
+/// Identifies a subscription either by the id web3 was given or by its normalized hex form.
+#[derive(Debug)]
+enum SubscriptionKey {
+    Web3(api::SubscriptionId),
+    #[cfg_attr(not(feature = "ethers"), allow(dead_code))]
+    Normalized(String),
+}
+
+struct Pending {
+    request: String,
+    // whether the request may be sent again after a reconnect
+    replayable: bool,
+    kind: PendingKind,
+}
+
+enum PendingKind {
+    Call {
+        subscribe: Option<Value>,
+        sender: oneshot::Sender<Result<Value, Failure>>,
+    },
+    // re-issuing the subscription with this key after a reconnect
+    Resubscribe(String),
+}
+
+struct Subscription {
+    // the id returned by the node when the caller subscribed, which stays the same across
+    // reconnects
+    id: String,
+    // the id of the subscription on the current connection
+    server_id: String,
+    params: Value,
+    sink: Option<mpsc::UnboundedSender<Value>>,
+    // the latest notifications received before the caller started listening, at most
+    // MAX_BACKLOG
+    backlog: VecDeque<Value>,
+}
+
+#[derive(Deserialize)]
+struct Incoming {
+    id: Option<RequestId>,
+    result: Option<Value>,
+    error: Option<jsonrpc_core::Error>,
+    params: Option<Notification>,
+}
+
+#[derive(Deserialize)]
+struct Notification {
+    subscription: String,
+    result: Value,
+}
+
+#[derive(Clone)]
+struct Connector {
+    network: Network,
+    client_id: Secret,
+    auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
+    // extra headers for the handshake request
+    headers: HeaderMap,
+}
+
+impl Connector {
+    async fn connect(&self) -> Result<Socket, anyhow::Error> {
+        let mut request = self.network.ws_url().into_client_request()?;
+        let headers = request.headers_mut();
+        // extra headers never replace the handshake headers or the ones set below
+        for (name, value) in &self.headers {
+            if !headers.contains_key(name) {
+                headers.append(name, value.clone());
+            }
+        }
+        if self.network.endpoint_kind == EndpointKind::Bitski {
+            headers.insert("X-API-Key", self.client_id.header_value()?);
+        }
+        headers.insert("User-Agent", HeaderValue::from_str(&USER_AGENT)?);
+
+        match self.auth_token_provider.get_access_token().await {
+            Ok(token) => {
+                headers.insert(
+                    "Authorization",
+                    sensitive_header(&format!("Bearer {}", token))?,
+                );
+            }
+            Err(_error) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("Connecting without an access token: {:?}", _error);
+            }
+        }
+
+        let (socket, _response) = tokio_tungstenite::connect_async(request).await?;
+        Ok(socket)
+    }
+}
+
+impl BitskiWs {
+    /// Connects to the WebSocket endpoint of `network`, reconnecting with the default
+    /// [ReconnectPolicy] when the connection drops.
+    ///
+    /// The access token is sent as a bearer token if `auth_token_provider` can provide one.
+    pub async fn connect(
+        network: &Network,
+        client_id: &dyn ToString,
+        auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
+    ) -> Result<Self, anyhow::Error> {
+        Self::connect_with_policy(
+            network,
+            client_id,
+            auth_token_provider,
+            ReconnectPolicy::default(),
+        )
+        .await
+    }
+
+    /// Connects to the WebSocket endpoint of `network`, reconnecting according to `policy` when
+    /// the connection drops.
+    pub async fn connect_with_policy(
+        network: &Network,
+        client_id: &dyn ToString,
+        auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
+        policy: ReconnectPolicy,
+    ) -> Result<Self, anyhow::Error> {
+        Self::connect_with_options(
+            network,
+            client_id,
+            auth_token_provider,
+            policy,
+            HttpClientOptions::default(),
+        )
+        .await
+    }
+
+    /// Connects like [BitskiWs::connect_with_policy], sending the extra headers of `options`
+    /// with every handshake request. The customizer of `options` configures HTTP clients only,
+    /// and isn't used for WebSocket connections.
+    pub async fn connect_with_options(
+        network: &Network,
+        client_id: &dyn ToString,
+        auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
+        policy: ReconnectPolicy,
+        options: HttpClientOptions,
+    ) -> Result<Self, anyhow::Error> {
+        let connector = Connector {
+            network: network.clone(),
+            client_id: Secret::new(client_id.to_string()),
+            auth_token_provider,
+            headers: options.headers,
+        };
+        let socket = connector.connect().await?;
+        let id = Arc::new(AtomicUsize::new(0));
+        let (instructions, receiver) = mpsc::unbounded();
+        let task = Task {
+            connector,
+            policy,
+            id: id.clone(),
+            pending: HashMap::new(),
+            subscriptions: HashMap::new(),
+            server_ids: HashMap::new(),
+            gaps: Vec::new(),
+            last_block: None,
+        };
+        tokio::spawn(task.run(socket, receiver));
+
+        Ok(BitskiWs {
+            network: network.clone(),
+            instructions,
+            id,
+        })
+    }
+
+    /// Returns a stream that yields a [Gap] every time the transport reconnects.
+    pub fn gaps(&self) -> mpsc::UnboundedReceiver<Gap> {
+        let (sink, stream) = mpsc::unbounded();
+        // if the task has shut down the sink is dropped and the stream ends
+        let _ = self.instructions.unbounded_send(Instruction::Gaps { sink });
+        stream
+    }
+
+    fn dispatch(
+        &self,
+        id: RequestId,
+        request: String,
+        subscribe: Option<Value>,
+    ) -> BoxFuture<'static, Result<Value, Failure>> {
+        let (sender, receiver) = oneshot::channel();
+        let sent = self.instructions.unbounded_send(Instruction::Request {
+            id,
+            request,
+            subscribe,
+            sender,
+        });
+        Box::pin(async move {
+            if sent.is_err() {
+                return Err(Failure::Closed);
+            }
+            receiver.await.unwrap_or(Err(Failure::Closed))
+        })
+    }
+
+    fn listen(&self, id: SubscriptionKey) -> Result<mpsc::UnboundedReceiver<Value>, Failure> {
+        let (sink, stream) = mpsc::unbounded();
+        self.instructions
+            .unbounded_send(Instruction::Subscribe { id, sink })
+            .map_err(|_error| Failure::Closed)?;
+        Ok(stream)
+    }
+
+    fn forget(&self, id: SubscriptionKey) -> Result<(), Failure> {
+        self.instructions
+            .unbounded_send(Instruction::Unsubscribe { id })
+            .map_err(|_error| Failure::Closed)
+    }
+}
+
+/// Normalizes a subscription id so ids from the node and from ethers' `U256` compare equal.
+fn subscription_key(id: &str) -> String {
+    let id = id.to_lowercase();
+    match id.strip_prefix("0x") {
+        Some(digits) => {
+            let digits = digits.trim_start_matches('0');
+            format!("0x{}", if digits.is_empty() { "0" } else { digits })
+        }
+        None => id,
+    }
+}
+
+enum Exit {
+    Shutdown,
+    Disconnected,
+}
+
+struct Task {
+    connector: Connector,
+    policy: ReconnectPolicy,
+    id: Arc<AtomicUsize>,
+    pending: HashMap<RequestId, Pending>,
+    // keyed by the normalized id the caller knows the subscription by
+    subscriptions: HashMap<String, Subscription>,
+    // maps the normalized ids used by the current connection to subscription keys
+    server_ids: HashMap<String, String>,
+    gaps: Vec<mpsc::UnboundedSender<Gap>>,
+    last_block: Option<u64>,
+}
+
+impl Task {
+    async fn run(
+        mut self,
+        mut socket: Socket,
+        mut instructions: mpsc::UnboundedReceiver<Instruction>,
+    ) {
+        loop {
+            if let Exit::Shutdown = self.drive(&mut socket, &mut instructions).await {
+                let _ = socket.close(None).await;
+                return;
+            }
+
+            let disconnected_at = Instant::now();
+            socket = match self.reconnect().await {
+                Some(socket) => socket,
+                None => return,
+            };
+
+            let gap = Gap {
+                last_block: self.last_block,
+                downtime: disconnected_at.elapsed(),
+            };
+            self.gaps
+                .retain(|sink| sink.unbounded_send(gap.clone()).is_ok());
+        }
+    }
+
+    /// Processes instructions and messages until the connection drops or every handle is gone.
+    async fn drive(
+        &mut self,
+        socket: &mut Socket,
+        instructions: &mut mpsc::UnboundedReceiver<Instruction>,
+    ) -> Exit {
+        loop {
+            tokio::select! {
+                instruction = instructions.next() => match instruction {
+                    Some(Instruction::Request { id, request, subscribe, sender }) => {
+                        let (request, replayable) = self.prepare_request(request);
+                        let sent = socket.send(Message::Text(request.clone())).await;
+                        let kind = PendingKind::Call { subscribe, sender };
+                        self.pending.insert(id, Pending { request, replayable, kind });
+                        if let Err(_error) = sent {
+                            #[cfg(feature = "tracing")]
+                            tracing::warn!("Failed to send WebSocket request: {:?}", _error);
+                            return Exit::Disconnected;
+                        }
+                    }
+                    Some(Instruction::Subscribe { id, sink }) => {
+                        // an unknown id drops the sink, which ends the caller's stream
+                        let key = self.find_subscription(&id);
+                        let subscription = key.and_then(|key| self.subscriptions.get_mut(&key));
+                        if let Some(subscription) = subscription {
+                            for notification in subscription.backlog.drain(..) {
+                                let _ = sink.unbounded_send(notification);
+                            }
+                            subscription.sink = Some(sink);
+                        }
+                    }
+                    Some(Instruction::Unsubscribe { id }) => {
+                        if let Some(key) = self.find_subscription(&id) {
+                            self.subscriptions.remove(&key);
+                            self.server_ids.retain(|_, value| *value != key);
+                        }
+                    }
+                    Some(Instruction::Gaps { sink }) => self.gaps.push(sink),
+                    None => return Exit::Shutdown,
+                },
+                message = socket.next() => match message {
+                    Some(Ok(Message::Text(text))) => self.handle_message(&text),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!("WebSocket connection closed: {:?}", message);
+                        return Exit::Disconnected;
+                    }
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+    }
+
+    /// Reconnects with exponential backoff, then re-sends pending requests and re-issues every
+    /// subscription. Returns `None` once the policy gives up.
+    async fn reconnect(&mut self) -> Option<Socket> {
+        let mut backoff = self.policy.initial_backoff;
+        let mut attempts = 0;
+
+        loop {
+            if matches!(self.policy.max_attempts, Some(max) if attempts >= max) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Giving up reconnecting after {} attempts", attempts);
+                return None;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.policy.max_backoff);
+            attempts += 1;
+
+            let mut socket = match self.connector.connect().await {
+                Ok(socket) => socket,
+                Err(_error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Failed to reconnect WebSocket: {:?}", _error);
+                    continue;
+                }
+            };
+
+            if self.resume(&mut socket).await.is_ok() {
+                return Some(socket);
+            }
+        }
+    }
+
+    async fn resume(&mut self, socket: &mut Socket) -> Result<(), anyhow::Error> {
+        // requests sent on the old connection that never got a response are sent again, unless
+        // they are unsafe to send twice. Resubscriptions are never replayable, since every
+        // subscription is re-issued below.
+        let interrupted: Vec<RequestId> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| !pending.replayable)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in interrupted {
+            let kind = self.pending.remove(&id).map(|pending| pending.kind);
+            if let Some(PendingKind::Call { sender, .. }) = kind {
+                let _ = sender.send(Err(Failure::Interrupted));
+            }
+        }
+        for pending in self.pending.values() {
+            socket.send(Message::Text(pending.request.clone())).await?;
+        }
+
+        self.server_ids.clear();
+        let keys: Vec<String> = self.subscriptions.keys().cloned().collect();
+        for key in keys {
+            let id = self.id.fetch_add(1, Ordering::AcqRel);
+            let params = self.subscriptions[&key].params.clone();
+            let request = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "eth_subscribe",
+                "params": params,
+            })
+            .to_string();
+            socket.send(Message::Text(request.clone())).await?;
+            let kind = PendingKind::Resubscribe(key);
+            let replayable = false;
+            self.pending.insert(
+                id,
+                Pending {
+                    request,
+                    replayable,
+                    kind,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the request to send and whether it may be re-sent after a reconnect.
+    ///
+    /// Callers unsubscribe with the id they subscribed with, possibly in another format, such as
+    /// ethers' `U256`, so `eth_unsubscribe` is rewritten to the id of the subscription on the
+    /// current connection.
+    fn prepare_request(&self, request: String) -> (String, bool) {
+        let Ok(mut call) = serde_json::from_str::<Value>(&request) else {
+            return (request, true);
+        };
+        let method = call["method"].as_str().unwrap_or_default();
+        let replayable = !NON_IDEMPOTENT_METHODS.contains(&method);
+        if method != "eth_unsubscribe" {
+            return (request, replayable);
+        }
+
+        let subscription = call["params"][0]
+            .as_str()
+            .and_then(|id| self.subscriptions.get(&subscription_key(id)));
+        match subscription {
+            Some(subscription) if call["params"][0] != subscription.server_id.as_str() => {
+                call["params"][0] = Value::String(subscription.server_id.clone());
+                (call.to_string(), replayable)
+            }
+            _ => (request, replayable),
+        }
+    }
+
+    /// Finds the key of a subscription created on this transport.
+    fn find_subscription(&self, id: &SubscriptionKey) -> Option<String> {
+        match id {
+            SubscriptionKey::Web3(id) => self
+                .subscriptions
+                .iter()
+                .find(|(_, subscription)| api::SubscriptionId::from(subscription.id.clone()) == *id)
+                .map(|(key, _)| key.clone()),
+            SubscriptionKey::Normalized(key) => {
+                self.subscriptions.contains_key(key).then(|| key.clone())
+            }
+        }
+    }
+
+    fn handle_message(&mut self, text: &str) {
+        let messages: Vec<Incoming> = match serde_json::from_str(text) {
+            Ok(Value::Array(values)) => values
+                .into_iter()
+                .filter_map(|value| serde_json::from_value(value).ok())
+                .collect(),
+            Ok(value) => serde_json::from_value(value).into_iter().collect(),
+            Err(_error) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Received invalid WebSocket message: {:?}", _error);
+                return;
+            }
+        };
+
+        for message in messages {
+            if let Some(notification) = message.params {
+                self.handle_notification(notification);
+            } else if let Some(pending) = message.id.and_then(|id| self.pending.remove(&id)) {
+                let result = match message.error {
+                    Some(error) => Err(Failure::Rpc(error)),
+                    None => Ok(message.result.unwrap_or(Value::Null)),
+                };
+                self.handle_response(pending.kind, result);
+            }
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) {
+        let server_id = subscription_key(&notification.subscription);
+        let Some(subscription) = self
+            .server_ids
+            .get(&server_id)
+            .and_then(|key| self.subscriptions.get_mut(key))
+        else {
+            return;
+        };
+
+        if subscription.params.get(0) == Some(&Value::String("newHeads".to_string())) {
+            let number = notification.result.get("number").and_then(Value::as_str);
+            if let Some(number) = number.and_then(parse_quantity) {
+                self.last_block = self.last_block.max(Some(number));
+            }
+        }
+
+        match &subscription.sink {
+            Some(sink) => {
+                let _ = sink.unbounded_send(notification.result);
+            }
+            None => {
+                if subscription.backlog.len() == MAX_BACKLOG {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("Dropping the oldest notification of {}", subscription.id);
+                    subscription.backlog.pop_front();
+                }
+                subscription.backlog.push_back(notification.result);
+            }
+        }
+    }
+
+    fn handle_response(&mut self, kind: PendingKind, result: Result<Value, Failure>) {
+        match (kind, result) {
+            (PendingKind::Call { subscribe, sender }, result) => {
+                if let (Some(params), Ok(Value::String(id))) = (subscribe, &result) {
+                    let key = subscription_key(id);
+                    self.server_ids.insert(key.clone(), key.clone());
+                    self.subscriptions.insert(
+                        key,
+                        Subscription {
+                            id: id.clone(),
+                            server_id: id.clone(),
+                            params,
+                            sink: None,
+                            backlog: VecDeque::new(),
+                        },
+                    );
+                }
+                let _ = sender.send(result);
+            }
+            (PendingKind::Resubscribe(key), Ok(Value::String(id))) => {
+                if let Some(subscription) = self.subscriptions.get_mut(&key) {
+                    self.server_ids.insert(subscription_key(&id), key);
+                    subscription.server_id = id;
+                }
+            }
+            (PendingKind::Resubscribe(key), _result) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("Failed to resubscribe {}: {:?}", key, _result);
+                // dropping the subscription ends the caller's stream
+                self.subscriptions.remove(&key);
+            }
+        }
+    }
+}
+
+fn parse_quantity(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+impl From<Failure> for web3::error::Error {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::Rpc(error) => web3::error::Error::Rpc(error),
+            Failure::Closed => web3::error::Error::Transport(TransportError::Message(
+                "WebSocket connection closed".to_string(),
+            )),
+            Failure::Interrupted => web3::error::Error::Transport(TransportError::Message(
+                "WebSocket connection dropped before a response, and the request is unsafe to \
+                 re-send"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+impl Transport for BitskiWs {
+    type Out = BoxFuture<'static, web3::error::Result<jsonrpc_core::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.id.fetch_add(1, Ordering::AcqRel);
+        let request = helpers::build_request(id, method, params);
+        (id, request)
+    }
+
+    fn send(&self, id: RequestId, request: Call) -> Self::Out {
+        let subscribe = match &request {
+            Call::MethodCall(call) if call.method == "eth_subscribe" => {
+                serde_json::to_value(&call.params).ok()
+            }
+            _ => None,
+        };
+        let request = helpers::to_string(&request);
+        self.dispatch(id, request, subscribe)
+            .map(|result| result.map_err(Into::into))
+            .boxed()
+    }
+}
+
+impl BatchTransport for BitskiWs {
+    type Batch =
+        BoxFuture<'static, web3::error::Result<Vec<web3::error::Result<jsonrpc_core::Value>>>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let responses: Vec<_> = requests
+            .into_iter()
+            .map(|(id, request)| self.send(id, request))
+            .collect();
+        join_all(responses).map(Ok).boxed()
+    }
+}
+
+impl DuplexTransport for BitskiWs {
+    type NotificationStream = mpsc::UnboundedReceiver<Value>;
+
+    fn subscribe(&self, id: api::SubscriptionId) -> web3::error::Result<Self::NotificationStream> {
+        Ok(self.listen(SubscriptionKey::Web3(id))?)
+    }
+
+    fn unsubscribe(&self, id: api::SubscriptionId) -> web3::error::Result<()> {
+        Ok(self.forget(SubscriptionKey::Web3(id))?)
+    }
+}
+
+#[cfg(feature = "ethers")]
+mod ethers_impl {
+    use super::{subscription_key, BitskiWs, Failure, SubscriptionKey};
+    use ethers::prelude::{JsonRpcClient, JsonRpcError, PubsubClient, WsClientError, U256};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json::value::RawValue;
+    use serde_json::Value;
+    use std::fmt::Debug;
+    use std::sync::atomic::Ordering;
+    use web3::futures::channel::mpsc;
+    use web3::futures::stream::Map;
+    use web3::futures::StreamExt;
+
+    impl From<Failure> for WsClientError {
+        fn from(failure: Failure) -> Self {
+            match failure {
+                Failure::Rpc(error) => WsClientError::JsonRpcError(JsonRpcError {
+                    code: error.code.code(),
+                    message: error.message,
+                    data: error.data,
+                }),
+                Failure::Closed | Failure::Interrupted => WsClientError::UnexpectedClose,
+            }
+        }
+    }
+
+    fn to_raw_value(value: Value) -> Box<RawValue> {
+        serde_json::value::to_raw_value(&value).expect("JSON values are always serializable")
+    }
+
+    #[async_trait::async_trait]
+    impl JsonRpcClient for BitskiWs {
+        type Error = WsClientError;
+
+        async fn request<T: Debug + Serialize + Send + Sync, R: DeserializeOwned + Send>(
+            &self,
+            method: &str,
+            params: T,
+        ) -> Result<R, WsClientError> {
+            let id = self.id.fetch_add(1, Ordering::AcqRel);
+            let request = serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": method,
+                "params": params,
+            });
+            let subscribe = (method == "eth_subscribe").then(|| request["params"].clone());
+            let result = self.dispatch(id, request.to_string(), subscribe).await?;
+            Ok(serde_json::from_value(result)?)
+        }
+    }
+
+    impl PubsubClient for BitskiWs {
+        type NotificationStream = Map<mpsc::UnboundedReceiver<Value>, fn(Value) -> Box<RawValue>>;
+
+        fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+            let id = subscription_key(&format!("{:#x}", id.into()));
+            let stream = self.listen(SubscriptionKey::Normalized(id))?;
+            Ok(stream.map(to_raw_value as fn(Value) -> Box<RawValue>))
+        }
+
+        fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+            let id = subscription_key(&format!("{:#x}", id.into()));
+            Ok(self.forget(SubscriptionKey::Normalized(id))?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitskiWs, Connector, Gap, PendingKind, ReconnectPolicy, Task, MAX_BACKLOG};
+    use crate::http_client::HttpClientOptions;
+    use crate::secret::Secret;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+    use tokio_tungstenite::tungstenite::http::{HeaderMap, HeaderName, HeaderValue};
+    use tokio_tungstenite::tungstenite::Message;
+    use web3::api::SubscriptionId;
+    use web3::futures::channel::oneshot;
+    use web3::futures::{SinkExt, StreamExt};
+    use web3::{DuplexTransport, Transport};
+
+    /// Starts a WebSocket server that answers `eth_blockNumber`, `eth_subscribe` and
+    /// `eth_unsubscribe`, and pushes a single `newHeads` notification for every subscription it
+    /// creates. Every connection uses its own subscription id and block number, and all but the
+    /// last of `connections` are closed after the notification has been sent. Connections are
+    /// also closed on `eth_sendRawTransaction`, without a response.
+    async fn stand_in_server(connections: u64) -> Network {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            for connection in 1..=connections {
+                let (stream, _) = listener.accept().await.unwrap();
+                let callback = |request: &Request, response: Response| {
+                    assert_eq!(request.headers()["X-API-Key"], "test-client-id");
+                    assert_eq!(request.headers()["Authorization"], "Bearer test-token");
+                    Ok(response)
+                };
+                let mut socket = tokio_tungstenite::accept_hdr_async(stream, callback)
+                    .await
+                    .unwrap();
+
+                let subscription = format!("0x00{:x}", connection);
+                while let Some(Ok(Message::Text(text))) = socket.next().await {
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    let result = match request["method"].as_str().unwrap() {
+                        "eth_blockNumber" => json!("0x10"),
+                        "eth_subscribe" => {
+                            assert_eq!(request["params"], json!(["newHeads"]));
+                            json!(subscription)
+                        }
+                        "eth_unsubscribe" => json!(request["params"] == json!([subscription])),
+                        "eth_sendRawTransaction" => {
+                            socket.close(None).await.unwrap();
+                            break;
+                        }
+                        method => panic!("unexpected method {}", method),
+                    };
+                    let response = json!({"jsonrpc": "2.0", "id": request["id"], "result": result});
+                    socket
+                        .send(Message::Text(response.to_string()))
+                        .await
+                        .unwrap();
+
+                    if request["method"] == "eth_subscribe" {
+                        let notification = json!({
+                            "jsonrpc": "2.0",
+                            "method": "eth_subscription",
+                            "params": {
+                                "subscription": subscription,
+                                "result": {"number": format!("0x{:x}", 0x10 + connection)},
+                            },
+                        });
+                        socket
+                            .send(Message::Text(notification.to_string()))
+                            .await
+                            .unwrap();
+                        if connection < connections {
+                            socket.close(None).await.unwrap();
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Network::new(format!("http://{}", address), 1337).with_endpoint_kind(EndpointKind::Bitski)
+    }
+
+    async fn connect(network: &Network) -> BitskiWs {
+        let policy = ReconnectPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..ReconnectPolicy::default()
+        };
+        BitskiWs::connect_with_policy(
+            network,
+            &"test-client-id",
+            Arc::new("test-token".to_string()),
+            policy,
+        )
+        .await
+        .expect("could not connect")
+    }
+
+    #[tokio::test]
+    async fn test_request_and_subscribe() {
+        let ws = connect(&stand_in_server(1).await).await;
+
+        let block_number = ws.execute("eth_blockNumber", vec![]).await.unwrap();
+        assert_eq!(block_number, json!("0x10"));
+
+        let id = ws
+            .execute("eth_subscribe", vec![json!("newHeads")])
+            .await
+            .unwrap();
+        let id = SubscriptionId::from(id.as_str().unwrap().to_string());
+        let mut notifications = DuplexTransport::subscribe(&ws, id).unwrap();
+        assert_eq!(notifications.next().await, Some(json!({"number": "0x11"})));
+    }
+
+    #[tokio::test]
+    async fn test_resubscribe_after_reconnect() {
+        let ws = connect(&stand_in_server(2).await).await;
+        let mut gaps = ws.gaps();
+
+        let id = ws
+            .execute("eth_subscribe", vec![json!("newHeads")])
+            .await
+            .unwrap();
+        let id = SubscriptionId::from(id.as_str().unwrap().to_string());
+        let mut notifications = DuplexTransport::subscribe(&ws, id).unwrap();
+        assert_eq!(notifications.next().await, Some(json!({"number": "0x11"})));
+
+        let Gap { last_block, .. } = gaps.next().await.unwrap();
+        assert_eq!(last_block, Some(0x11));
+
+        // the re-issued subscription has a new id on the node but feeds the same stream
+        assert_eq!(notifications.next().await, Some(json!({"number": "0x12"})));
+        let block_number = ws.execute("eth_blockNumber", vec![]).await.unwrap();
+        assert_eq!(block_number, json!("0x10"));
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_after_reconnect() {
+        let ws = connect(&stand_in_server(2).await).await;
+        let mut gaps = ws.gaps();
+
+        let id = ws
+            .execute("eth_subscribe", vec![json!("newHeads")])
+            .await
+            .unwrap();
+        let subscription = SubscriptionId::from(id.as_str().unwrap().to_string());
+        let mut notifications = DuplexTransport::subscribe(&ws, subscription.clone()).unwrap();
+        assert_eq!(notifications.next().await, Some(json!({"number": "0x11"})));
+        gaps.next().await.unwrap();
+        assert_eq!(notifications.next().await, Some(json!({"number": "0x12"})));
+
+        // the caller's id is mapped to the id of the re-issued subscription
+        let unsubscribed = ws.execute("eth_unsubscribe", vec![id]).await.unwrap();
+        assert_eq!(unsubscribed, json!(true));
+        DuplexTransport::unsubscribe(&ws, subscription).unwrap();
+        assert_eq!(notifications.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_transactions_are_not_resent() {
+        let ws = connect(&stand_in_server(2).await).await;
+        let mut gaps = ws.gaps();
+
+        let result = ws
+            .execute("eth_sendRawTransaction", vec![json!("0x00")])
+            .await;
+        assert!(result.is_err());
+        gaps.next().await.unwrap();
+
+        let block_number = ws.execute("eth_blockNumber", vec![]).await.unwrap();
+        assert_eq!(block_number, json!("0x10"));
+    }
+
+    #[cfg(feature = "ethers")]
+    #[tokio::test]
+    async fn test_ethers_subscribe_and_unsubscribe() {
+        use ethers::prelude::{Middleware, Provider, PubsubClient};
+
+        let ws = connect(&stand_in_server(1).await).await;
+        let provider = Provider::new(ws.clone());
+
+        let mut notifications = provider.subscribe::<_, Value>(["newHeads"]).await.unwrap();
+        assert_eq!(notifications.next().await, Some(json!({"number": "0x11"})));
+
+        // ethers sends the id as a `U256`, which is rewritten to the id the node returned
+        let id = notifications.id;
+        assert!(notifications.unsubscribe().await.unwrap());
+        drop(notifications);
+
+        // dropping the stream unsubscribes, so listening again ends immediately
+        let mut notifications = PubsubClient::subscribe(&ws, id).unwrap();
+        assert!(notifications.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_extra_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let callback = move |request: &Request, response: Response| {
+                sender.send(request.headers().clone()).unwrap();
+                Ok(response)
+            };
+            let _socket = tokio_tungstenite::accept_hdr_async(stream, callback)
+                .await
+                .unwrap();
+            receiver.recv().unwrap()
+        });
+
+        let options = HttpClientOptions::default()
+            .with_header(
+                HeaderName::from_static("x-tenant-id"),
+                HeaderValue::from_static("tenant"),
+            )
+            .with_header(
+                HeaderName::from_static("x-api-key"),
+                HeaderValue::from_static("overridden"),
+            )
+            .with_header(
+                HeaderName::from_static("upgrade"),
+                HeaderValue::from_static("overridden"),
+            );
+        let network = Network::new(format!("http://{}", address), 1337)
+            .with_endpoint_kind(EndpointKind::Bitski);
+        BitskiWs::connect_with_options(
+            &network,
+            &"test-client-id",
+            Arc::new(()),
+            ReconnectPolicy::default(),
+            options,
+        )
+        .await
+        .expect("could not connect");
+
+        let headers = server.await.unwrap();
+        assert_eq!(headers["x-tenant-id"], "tenant");
+        assert_eq!(headers["x-api-key"], "test-client-id");
+        assert_eq!(headers["upgrade"], "websocket");
+    }
+
+    #[test]
+    fn test_backlog_is_capped() {
+        let connector = Connector {
+            network: Network::new("http://localhost", 1337),
+            client_id: Secret::new("test-client-id".to_string()),
+            auth_token_provider: Arc::new(()),
+            headers: HeaderMap::new(),
+        };
+        let mut task = Task {
+            connector,
+            policy: ReconnectPolicy::default(),
+            id: Default::default(),
+            pending: HashMap::new(),
+            subscriptions: HashMap::new(),
+            server_ids: HashMap::new(),
+            gaps: Vec::new(),
+            last_block: None,
+        };
+        let (sender, _receiver) = oneshot::channel();
+        let kind = PendingKind::Call {
+            subscribe: Some(json!(["newHeads"])),
+            sender,
+        };
+        task.handle_response(kind, Ok(json!("0x1")));
+
+        // nobody listens to the subscription, so only the latest notifications are kept
+        for number in 0..MAX_BACKLOG + 2 {
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "eth_subscription",
+                "params": {"subscription": "0x1", "result": number},
+            });
+            task.handle_message(&notification.to_string());
+        }
+        let backlog = &task.subscriptions["0x1"].backlog;
+        assert_eq!(backlog.len(), MAX_BACKLOG);
+        assert_eq!(backlog.front(), Some(&json!(2)));
+        assert_eq!(backlog.back(), Some(&json!(MAX_BACKLOG + 1)));
+    }
+}
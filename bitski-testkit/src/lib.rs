@@ -0,0 +1,785 @@
+#![doc = include_str!("../README.md")]
+
+use base64::Engine;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, Bytes, TransactionRequest};
+use ethers::utils::keccak256;
+use hyper::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use url::form_urlencoded;
+
+/// The private key of the default signing account, the first account of common local dev nodes
+/// such as Anvil and Hardhat.
+pub const DEFAULT_PRIVATE_KEY: &str =
+    "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+/// The EIP-1193 code for a request that isn't authorized.
+const UNAUTHORIZED: i64 = 4100;
+
+/// Methods that need an access token and are answered with the local key.
+const SIGNING_METHODS: [&str; 6] = [
+    "eth_accounts",
+    "eth_requestAccounts",
+    "eth_sign",
+    "personal_sign",
+    "eth_signTransaction",
+    "eth_sendTransaction",
+];
+
+/// A JSON-RPC error returned by the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn invalid_params(message: impl ToString) -> Self {
+        Self::new(-32602, message.to_string())
+    }
+
+    fn internal(message: impl ToString) -> Self {
+        Self::new(-32603, message.to_string())
+    }
+}
+
+/// A call received by the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedCall {
+    /// The chain in the request path, such as `mainnet` or `goerli`.
+    pub chain: String,
+    pub method: String,
+    pub params: Value,
+    /// Whether the call was sent to the REST route instead of the JSON-RPC endpoint.
+    pub rest: bool,
+    /// Whether the call had a valid access token.
+    pub authorized: bool,
+    /// The `X-API-Key` header of the request, if it had one.
+    pub api_key: Option<String>,
+    /// Whether the call was answered with `304 Not Modified`, because the `If-None-Match` header
+    /// matched the `ETag` of the response.
+    pub not_modified: bool,
+}
+
+/// Configures and starts a [TestServer].
+#[derive(Debug)]
+pub struct TestServerBuilder {
+    credential_id: String,
+    credential_secret: String,
+    access_token: String,
+    wallet: LocalWallet,
+    chain_id: u64,
+    responses: HashMap<String, Value>,
+    cache_control: HashMap<String, String>,
+    rest_methods: Option<HashSet<String>>,
+    rest_capabilities: Option<Vec<String>>,
+    rest_not_found: HashSet<String>,
+    rest_failures: HashMap<String, (u16, String)>,
+    upstream: Option<String>,
+}
+
+impl Default for TestServerBuilder {
+    fn default() -> Self {
+        TestServerBuilder {
+            credential_id: "test-credential-id".to_string(),
+            credential_secret: "test-credential-secret".to_string(),
+            access_token: "test-access-token".to_string(),
+            wallet: DEFAULT_PRIVATE_KEY.parse().expect("default key is valid"),
+            chain_id: 1337,
+            responses: HashMap::new(),
+            cache_control: HashMap::new(),
+            rest_methods: None,
+            rest_capabilities: None,
+            rest_not_found: HashSet::new(),
+            rest_failures: HashMap::new(),
+            upstream: None,
+        }
+    }
+}
+
+impl TestServerBuilder {
+    /// Sets the client credentials the token endpoint accepts.
+    pub fn with_credentials(mut self, credential_id: &str, credential_secret: &str) -> Self {
+        self.credential_id = credential_id.to_string();
+        self.credential_secret = credential_secret.to_string();
+        self
+    }
+
+    /// Sets the access token the token endpoint issues, which signing methods require.
+    pub fn with_access_token(mut self, access_token: &str) -> Self {
+        self.access_token = access_token.to_string();
+        self
+    }
+
+    /// Signs with `wallet` instead of the default account.
+    pub fn with_wallet(mut self, wallet: LocalWallet) -> Self {
+        self.wallet = wallet;
+        self
+    }
+
+    /// Sets the chain id returned by `eth_chainId` and used to sign transactions.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    /// Answers every call to `method` with `result`.
+    pub fn with_response(mut self, method: &str, result: Value) -> Self {
+        self.responses.insert(method.to_string(), result);
+        self
+    }
+
+    /// Sets the `Cache-Control` header of REST responses to `method`.
+    pub fn with_cache_control(mut self, method: &str, cache_control: &str) -> Self {
+        self.cache_control
+            .insert(method.to_string(), cache_control.to_string());
+        self
+    }
+
+    /// Serves only `methods` on the REST route, answering other methods with `404 Not Found` and a
+    /// JSON-RPC "method not found" error. By default every method is served.
+    pub fn with_rest_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.rest_methods = Some(
+            methods
+                .into_iter()
+                .map(|method| method.to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Lists `methods` at `GET /v1/web3/{chain}/capabilities`, where providers read the methods
+    /// the REST route serves. By default there is no list, and that path answers
+    /// `404 Not Found`. Reading the list isn't recorded as a call.
+    pub fn with_rest_capabilities<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.rest_capabilities = Some(
+            methods
+                .into_iter()
+                .map(|method| method.to_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Answers `method` on the REST route with a plain `404 Not Found`, as for a missing resource,
+    /// while still serving it on the JSON-RPC endpoint.
+    pub fn with_rest_not_found(mut self, method: &str) -> Self {
+        self.rest_not_found.insert(method.to_string());
+        self
+    }
+
+    /// Answers `method` on the REST route with `status` and an HTML `body`, as a proxy in front of
+    /// the API would.
+    pub fn with_rest_failure(mut self, method: &str, status: u16, body: &str) -> Self {
+        self.rest_failures
+            .insert(method.to_string(), (status, body.to_string()));
+        self
+    }
+
+    /// Forwards calls without a canned response to the JSON-RPC endpoint at `url`, such as a
+    /// local dev node.
+    pub fn with_upstream(mut self, url: &str) -> Self {
+        self.upstream = Some(url.to_string());
+        self
+    }
+
+    /// Starts the server on a free local port. Must be called within a Tokio runtime.
+    pub async fn start(self) -> io::Result<TestServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+
+        let state = Arc::new(State {
+            basic_auth: basic_auth(&self.credential_id, &self.credential_secret),
+            wallet: self.wallet.with_chain_id(self.chain_id),
+            chain_id: self.chain_id,
+            upstream: self.upstream,
+            client: Client::new(),
+            calls: Mutex::new(Vec::new()),
+            responses: self.responses,
+            cache_control: self.cache_control,
+            rest_methods: self.rest_methods,
+            rest_capabilities: self.rest_capabilities,
+            rest_not_found: self.rest_not_found,
+            rest_failures: self.rest_failures,
+            credential_id: self.credential_id,
+            credential_secret: self.credential_secret,
+            access_token: self.access_token,
+        });
+
+        let service_state = state.clone();
+        let service = make_service_fn(move |_connection| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(state.handle(request).await) }
+                }))
+            }
+        });
+
+        let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+        let server = Server::from_tcp(listener)
+            .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?
+            .serve(service)
+            .with_graceful_shutdown(async {
+                let _ = shutdown_signal.await;
+            });
+        tokio::spawn(server);
+
+        Ok(TestServer {
+            address,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+}
+
+/// A local HTTP server that stands in for the Bitski API.
+///
+/// It serves the OAuth token endpoint at `/oauth2/token`, JSON-RPC at `/v1/web3/{chain}` and the
+/// REST route at `/v1/web3/{chain}/{method}?params=`. Signing methods need the access token issued
+/// by the token endpoint and are answered with a local key. Other calls are answered with canned
+/// responses, or forwarded to an upstream node. The server stops when it is dropped.
+#[derive(Debug)]
+pub struct TestServer {
+    address: SocketAddr,
+    state: Arc<State>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl TestServer {
+    pub fn builder() -> TestServerBuilder {
+        TestServerBuilder::default()
+    }
+
+    /// Starts a server with the default configuration.
+    pub async fn start() -> io::Result<Self> {
+        Self::builder().start().await
+    }
+
+    /// Returns the base URL of the server.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Returns the RPC URL for `chain`, to use with `Bitski::set_rpc_override`.
+    pub fn rpc_url(&self, chain: &str) -> String {
+        format!("{}/v1/web3/{}", self.url(), chain)
+    }
+
+    /// Returns the URL of the OAuth token endpoint.
+    pub fn token_url(&self) -> String {
+        format!("{}/oauth2/token", self.url())
+    }
+
+    pub fn credential_id(&self) -> &str {
+        &self.state.credential_id
+    }
+
+    pub fn credential_secret(&self) -> &str {
+        &self.state.credential_secret
+    }
+
+    pub fn access_token(&self) -> &str {
+        &self.state.access_token
+    }
+
+    /// Returns the address of the signing account.
+    pub fn address(&self) -> Address {
+        self.state.wallet.address()
+    }
+
+    /// Returns every call received, in order.
+    pub fn calls(&self) -> Vec<ReceivedCall> {
+        self.state
+            .calls
+            .lock()
+            .expect("calls lock poisoned")
+            .clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    credential_id: String,
+    credential_secret: String,
+    basic_auth: String,
+    access_token: String,
+    wallet: LocalWallet,
+    chain_id: u64,
+    upstream: Option<String>,
+    client: Client<hyper::client::HttpConnector>,
+    calls: Mutex<Vec<ReceivedCall>>,
+    responses: HashMap<String, Value>,
+    cache_control: HashMap<String, String>,
+    rest_methods: Option<HashSet<String>>,
+    rest_capabilities: Option<Vec<String>>,
+    rest_not_found: HashSet<String>,
+    rest_failures: HashMap<String, (u16, String)>,
+}
+
+impl State {
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let path = request.uri().path().to_string();
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+        match (request.method(), segments.as_slice()) {
+            (&Method::POST, ["oauth2", "token"]) => self.token(request).await,
+            (&Method::POST, ["v1", "web3", chain]) => {
+                let chain = chain.to_string();
+                self.json_rpc(&chain, request).await
+            }
+            (&Method::GET, ["v1", "web3", _chain, "capabilities"]) => {
+                match &self.rest_capabilities {
+                    Some(methods) => respond(StatusCode::OK, json!({ "methods": methods })),
+                    None => respond(StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+                }
+            }
+            (&Method::GET, ["v1", "web3", chain, method]) => {
+                let (chain, method) = (chain.to_string(), method.to_string());
+                self.rest(&chain, &method, request).await
+            }
+            _ => respond(StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+        }
+    }
+
+    async fn token(&self, request: Request<Body>) -> Response<Body> {
+        let header_matches = request
+            .headers()
+            .get(AUTHORIZATION)
+            .map_or(false, |header| *header == self.basic_auth);
+        let body = match hyper::body::to_bytes(request.into_body()).await {
+            Ok(body) => body,
+            Err(_) => {
+                return respond(
+                    StatusCode::BAD_REQUEST,
+                    json!({ "error": "invalid_request" }),
+                )
+            }
+        };
+        let form: HashMap<_, _> = form_urlencoded::parse(&body).into_owned().collect();
+        let body_matches = form.get("client_id") == Some(&self.credential_id)
+            && form.get("client_secret") == Some(&self.credential_secret);
+
+        if form.get("grant_type").map(String::as_str) != Some("client_credentials") {
+            return respond(
+                StatusCode::BAD_REQUEST,
+                json!({ "error": "unsupported_grant_type" }),
+            );
+        }
+        if !header_matches && !body_matches {
+            return respond(
+                StatusCode::UNAUTHORIZED,
+                json!({ "error": "invalid_client" }),
+            );
+        }
+        respond(
+            StatusCode::OK,
+            json!({
+                "access_token": self.access_token,
+                "token_type": "bearer",
+                "expires_in": 3600,
+            }),
+        )
+    }
+
+    async fn json_rpc(&self, chain: &str, request: Request<Body>) -> Response<Body> {
+        let authorized = self.is_authorized(&request);
+        let api_key = api_key(&request);
+        let body = match hyper::body::to_bytes(request.into_body()).await {
+            Ok(body) => body,
+            Err(error) => return respond_error(Value::Null, RpcError::internal(error)),
+        };
+        let body: Value = match serde_json::from_slice(&body) {
+            Ok(body) => body,
+            Err(error) => {
+                return respond_error(Value::Null, RpcError::new(-32700, error.to_string()))
+            }
+        };
+
+        match body {
+            Value::Array(calls) => {
+                let mut responses = Vec::new();
+                for call in calls {
+                    responses.push(self.json_rpc_call(chain, call, authorized, &api_key).await);
+                }
+                respond(StatusCode::OK, Value::Array(responses))
+            }
+            call => respond(
+                StatusCode::OK,
+                self.json_rpc_call(chain, call, authorized, &api_key).await,
+            ),
+        }
+    }
+
+    async fn json_rpc_call(
+        &self,
+        chain: &str,
+        call: Value,
+        authorized: bool,
+        api_key: &Option<String>,
+    ) -> Value {
+        let id = call.get("id").cloned().unwrap_or_default();
+        let Some(method) = call.get("method").and_then(Value::as_str) else {
+            return error_envelope(id, RpcError::new(-32600, "invalid request"));
+        };
+        let params = call.get("params").cloned().unwrap_or_else(|| json!([]));
+        self.record(ReceivedCall {
+            chain: chain.to_string(),
+            method: method.to_string(),
+            params: params.clone(),
+            rest: false,
+            authorized,
+            api_key: api_key.clone(),
+            not_modified: false,
+        });
+        match self.call(method, params, authorized).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => error_envelope(id, error),
+        }
+    }
+
+    /// Answers a call to the REST route, with an `ETag` for conditional requests.
+    async fn rest(&self, chain: &str, method: &str, request: Request<Body>) -> Response<Body> {
+        let authorized = self.is_authorized(&request);
+        let if_none_match = request.headers().get(IF_NONE_MATCH).cloned();
+        let query = request.uri().query().unwrap_or_default();
+        let params = form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "params")
+            .map(|(_, params)| serde_json::from_str(&params))
+            .unwrap_or(Ok(json!([])));
+        let mut received = ReceivedCall {
+            chain: chain.to_string(),
+            method: method.to_string(),
+            params: params.as_ref().cloned().unwrap_or_default(),
+            rest: true,
+            authorized,
+            api_key: api_key(&request),
+            not_modified: false,
+        };
+        let supported = self
+            .rest_methods
+            .as_ref()
+            .map_or(true, |methods| methods.contains(method));
+        if !supported {
+            self.record(received);
+            let error = RpcError::new(-32601, "method not supported on the REST route");
+            return respond(StatusCode::NOT_FOUND, json!({ "error": error }));
+        }
+        if self.rest_not_found.contains(method) {
+            self.record(received);
+            return respond(StatusCode::NOT_FOUND, json!({ "error": "not found" }));
+        }
+        if let Some((status, body)) = self.rest_failures.get(method) {
+            self.record(received);
+            return Response::builder()
+                .status(*status)
+                .header(CONTENT_TYPE, "text/html")
+                .body(Body::from(body.clone()))
+                .expect("response is valid");
+        }
+        let result = match params {
+            Ok(params) => self.call(method, params, authorized).await,
+            Err(error) => Err(RpcError::invalid_params(error)),
+        };
+
+        let result = match result {
+            Ok(result) => result,
+            Err(error) => {
+                self.record(received);
+                return respond(StatusCode::BAD_REQUEST, json!({ "error": error }));
+            }
+        };
+        let body = result.to_string();
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let etag = format!("\"{:x}\"", hasher.finish());
+        received.not_modified = if_none_match.map_or(false, |value| value == *etag);
+
+        let mut response = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .header(ETAG, &etag);
+        if let Some(cache_control) = self.cache_control.get(method) {
+            response = response.header(CACHE_CONTROL, cache_control);
+        }
+        let response = match received.not_modified {
+            true => response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty()),
+            false => response.status(StatusCode::OK).body(Body::from(body)),
+        };
+        self.record(received);
+        response.expect("response is valid")
+    }
+
+    fn record(&self, call: ReceivedCall) {
+        self.calls.lock().expect("calls lock poisoned").push(call);
+    }
+
+    fn is_authorized(&self, request: &Request<Body>) -> bool {
+        let expected = format!("Bearer {}", self.access_token);
+        request
+            .headers()
+            .get(AUTHORIZATION)
+            .map_or(false, |header| *header == *expected)
+    }
+
+    async fn call(&self, method: &str, params: Value, authorized: bool) -> Result<Value, RpcError> {
+        if SIGNING_METHODS.contains(&method) {
+            return match authorized {
+                true => self.sign(method, params).await,
+                false => Err(RpcError::new(UNAUTHORIZED, "Not signed in")),
+            };
+        }
+        if let Some(result) = self.responses.get(method) {
+            return Ok(result.clone());
+        }
+        if self.upstream.is_some() {
+            return self.forward(method, params).await;
+        }
+        match method {
+            "eth_chainId" => Ok(json!(format!("0x{:x}", self.chain_id))),
+            "net_version" => Ok(json!(self.chain_id.to_string())),
+            _ => Err(RpcError::new(
+                -32601,
+                format!("no response configured for {}", method),
+            )),
+        }
+    }
+
+    async fn sign(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let address = self.wallet.address();
+        match method {
+            "eth_accounts" | "eth_requestAccounts" => Ok(json!([address])),
+            "eth_sign" | "personal_sign" => {
+                let (account, message): (Address, Bytes) = match method {
+                    "eth_sign" => serde_json::from_value(params),
+                    _ => {
+                        serde_json::from_value(params).map(|(message, account)| (account, message))
+                    }
+                }
+                .map_err(RpcError::invalid_params)?;
+                self.check_account(account)?;
+                let signature = self
+                    .wallet
+                    .sign_message(message.as_ref())
+                    .await
+                    .map_err(RpcError::internal)?;
+                Ok(json!(format!("0x{}", signature)))
+            }
+            "eth_signTransaction" => Ok(json!(self.sign_transaction(params)?)),
+            "eth_sendTransaction" => {
+                let transaction = self.sign_transaction(params)?;
+                match self.upstream {
+                    Some(_) => {
+                        self.forward("eth_sendRawTransaction", json!([transaction]))
+                            .await
+                    }
+                    None => Ok(json!(ethers::types::H256(keccak256(&transaction)))),
+                }
+            }
+            _ => Err(RpcError::new(-32601, format!("unknown method {}", method))),
+        }
+    }
+
+    fn sign_transaction(&self, params: Value) -> Result<Bytes, RpcError> {
+        let (request,): (TransactionRequest,) =
+            serde_json::from_value(params).map_err(RpcError::invalid_params)?;
+        if let Some(from) = request.from {
+            self.check_account(from)?;
+        }
+        let mut transaction = TypedTransaction::Legacy(request);
+        if transaction.chain_id().is_none() {
+            transaction.set_chain_id(self.chain_id);
+        }
+        let signature = self
+            .wallet
+            .sign_transaction_sync(&transaction)
+            .map_err(RpcError::internal)?;
+        Ok(transaction.rlp_signed(&signature))
+    }
+
+    fn check_account(&self, account: Address) -> Result<(), RpcError> {
+        match account == self.wallet.address() {
+            true => Ok(()),
+            false => Err(RpcError::invalid_params(format!(
+                "unknown account {:?}",
+                account
+            ))),
+        }
+    }
+
+    async fn forward(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let upstream = self.upstream.as_deref().expect("checked by callers");
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let request = Request::post(upstream)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .map_err(RpcError::internal)?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(RpcError::internal)?;
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(RpcError::internal)?;
+        let mut response: Value = serde_json::from_slice(&body).map_err(RpcError::internal)?;
+        match response.get("error") {
+            Some(error) => Err(serde_json::from_value(error.clone()).map_err(RpcError::internal)?),
+            None => Ok(response["result"].take()),
+        }
+    }
+}
+
+/// Returns the `Authorization` header OAuth clients send for client credentials.
+fn basic_auth(credential_id: &str, credential_secret: &str) -> String {
+    let credentials = format!(
+        "{}:{}",
+        form_urlencoded::byte_serialize(credential_id.as_bytes()).collect::<String>(),
+        form_urlencoded::byte_serialize(credential_secret.as_bytes()).collect::<String>(),
+    );
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(credentials)
+    )
+}
+
+fn error_envelope(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+fn respond_error(id: Value, error: RpcError) -> Response<Body> {
+    respond(StatusCode::OK, error_envelope(id, error))
+}
+
+fn api_key(request: &Request<Body>) -> Option<String> {
+    request
+        .headers()
+        .get("X-API-Key")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("response is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestServer;
+    use bitski::Bitski;
+    use bitski_chain_models::networks::{EndpointKind, Network};
+    use bitski_provider::access_token_providers::ClientCredentialsAccessTokenProvider;
+    use bitski_provider::rest_web3_provider::RestWeb3Provider;
+    use ethers::providers::Middleware;
+    use ethers::types::TransactionRequest;
+    use serde_json::json;
+    use std::sync::Arc;
+    use web3::Transport;
+
+    #[tokio::test]
+    async fn test_end_to_end() {
+        let server = TestServer::builder()
+            .with_response("eth_blockNumber", json!("0x10"))
+            .start()
+            .await
+            .unwrap();
+        let credentials = ClientCredentialsAccessTokenProvider::new_with_token_url(
+            server.credential_id().to_string(),
+            server.credential_secret().to_string(),
+            None,
+            &server.token_url(),
+        )
+        .unwrap();
+        let mut bitski =
+            Bitski::new_with_access_token_provider(&"client-id", Arc::new(credentials));
+        bitski.set_rpc_override(server.rpc_url("mainnet"));
+        assert_eq!(
+            bitski.get_access_token().await.unwrap(),
+            server.access_token()
+        );
+
+        let web3 = bitski.get_web3("mainnet").unwrap();
+        assert_eq!(web3.eth().block_number().await.unwrap(), 16.into());
+        assert_eq!(web3.eth().chain_id().await.unwrap(), 1337.into());
+        assert_eq!(web3.eth().accounts().await.unwrap().len(), 1);
+
+        let ethers =
+            ethers::providers::Provider::new(bitski.get_ethers_provider("mainnet").unwrap());
+        let address = server.address();
+        let signature = ethers.sign(b"hello".to_vec(), &address).await.unwrap();
+        signature.verify("hello", address).unwrap();
+        let transaction = TransactionRequest::new()
+            .from(address)
+            .to(address)
+            .value(1)
+            .gas(21_000)
+            .gas_price(1)
+            .nonce(0);
+        ethers.send_transaction(transaction, None).await.unwrap();
+        assert_eq!(ethers.get_block_number().await.unwrap(), 16.into());
+
+        let mut unauthenticated = Bitski::new_unauthenticated(&"client-id");
+        unauthenticated.set_rpc_override(server.rpc_url("mainnet"));
+        let web3 = unauthenticated.get_web3("mainnet").unwrap();
+        assert!(web3.eth().accounts().await.is_err());
+
+        let network =
+            Network::new(server.rpc_url("mainnet"), 1).with_endpoint_kind(EndpointKind::Bitski);
+        let rest = RestWeb3Provider::new(network, &"client-id");
+        let result = rest.execute("eth_blockNumber", vec![]).await.unwrap();
+        assert_eq!(result, json!("0x10"));
+
+        let calls = server.calls();
+        assert!(calls.iter().all(|call| call.chain == "mainnet"));
+        // the override is a generic JSON-RPC endpoint, so only the REST provider used REST
+        let (last, others) = calls.split_last().unwrap();
+        assert!(last.rest);
+        assert!(others.iter().all(|call| !call.rest));
+        let methods: Vec<_> = calls.iter().map(|call| call.method.as_str()).collect();
+        assert!(methods.contains(&"eth_sendTransaction"));
+    }
+}
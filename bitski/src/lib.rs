@@ -1,20 +1,31 @@
 #![doc = include_str!("../README.md")]
 
 use anyhow::Error;
-use bitski_chain_models::networks::Network;
+use bitski_chain_models::networks::{EndpointKind, Network};
 use bitski_provider::access_token_providers::{
-    AccessTokenProvider, ClientCredentialsAccessTokenProvider,
+    AccessTokenProvider, ClientCredentialsAccessTokenProvider, DEFAULT_TOKEN_URL,
 };
+#[cfg(feature = "ethers")]
 use bitski_provider::ethers_provider::BitskiEthersProvider;
+use bitski_provider::http_client::HttpClientOptions;
+use bitski_provider::log_follower::{Checkpoint, LogFollower};
+use bitski_provider::polling::{self, PollingOptions};
+use bitski_provider::secret::Secret;
 use bitski_provider::web3_provider::BitskiWeb3Provider;
+use bitski_provider::ws_provider::{BitskiWs, ReconnectPolicy};
 use std::sync::Arc;
+use web3::futures::Stream;
+use web3::types::{Block, Filter, Log, H256};
 use web3::Web3;
 
 #[derive(Clone)]
 pub struct Bitski {
-    pub client_id: String,
+    pub client_id: Secret,
     pub auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
     pub rpc_override: Option<String>,
+    /// The kind of server behind `rpc_override`, or `None` to guess it from the URL.
+    pub rpc_override_kind: Option<EndpointKind>,
+    pub http_options: HttpClientOptions,
 }
 
 impl Bitski {
@@ -31,24 +42,59 @@ impl Bitski {
             scopes,
         ));
         Bitski {
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
             auth_token_provider,
             rpc_override: None,
+            rpc_override_kind: None,
+            http_options: HttpClientOptions::default(),
         }
     }
 
     /// Set the node url to use, which will override the standard [Network] `rpc_url`.
+    ///
+    /// Overrides on `bitski.com` hosts are treated as Bitski endpoints, and any other URL as a
+    /// generic JSON-RPC node that never receives the API key. Use [Bitski::set_rpc_override_kind]
+    /// to choose explicitly.
     pub fn set_rpc_override(&mut self, rpc_url: String) {
         self.rpc_override = Some(rpc_url);
     }
 
+    /// Set the kind of server behind the rpc override, for example [EndpointKind::Bitski] for a
+    /// Bitski staging or test server.
+    pub fn set_rpc_override_kind(&mut self, endpoint_kind: EndpointKind) {
+        self.rpc_override_kind = Some(endpoint_kind);
+    }
+
+    /// Set the options used to build the HTTP clients of every provider, such as a proxy, custom
+    /// root certificates or extra headers. WebSocket connections send the extra headers only.
+    pub fn set_http_options(&mut self, options: HttpClientOptions) {
+        self.http_options = options;
+    }
+
     /// Sets up Bitski with an existing access token
     pub fn new_with_access_token(client_id: &dyn ToString, access_token: &dyn ToString) -> Self {
-        let auth_token_provider = Arc::new(access_token.to_string());
+        let auth_token_provider = Arc::new(Secret::new(access_token.to_string()));
+        Bitski {
+            client_id: Secret::new(client_id.to_string()),
+            auth_token_provider,
+            rpc_override: None,
+            rpc_override_kind: None,
+            http_options: HttpClientOptions::default(),
+        }
+    }
+
+    /// Sets up Bitski with a custom access token provider, such as client credentials that are
+    /// exchanged at a different token URL.
+    pub fn new_with_access_token_provider(
+        client_id: &dyn ToString,
+        auth_token_provider: Arc<dyn AccessTokenProvider + Sync + Send>,
+    ) -> Self {
         Bitski {
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
             auth_token_provider,
             rpc_override: None,
+            rpc_override_kind: None,
+            http_options: HttpClientOptions::default(),
         }
     }
 
@@ -56,9 +102,11 @@ impl Bitski {
     pub fn new_unauthenticated(client_id: &dyn ToString) -> Self {
         let auth_token_provider = Arc::new(());
         Bitski {
-            client_id: client_id.to_string(),
+            client_id: Secret::new(client_id.to_string()),
             auth_token_provider,
             rpc_override: None,
+            rpc_override_kind: None,
+            http_options: HttpClientOptions::default(),
         }
     }
 
@@ -90,13 +138,22 @@ impl Bitski {
             false => Some(scopes),
         };
 
+        let token_url =
+            std::env::var("BITSKI_TOKEN_URL").unwrap_or_else(|_| DEFAULT_TOKEN_URL.to_string());
+
         match (credential_id, credential_secret) {
-            (Ok(credential_id), Ok(credential_secret)) => Ok(Bitski::new(
-                &client_id,
-                &credential_id,
-                &credential_secret,
-                scopes,
-            )),
+            (Ok(credential_id), Ok(credential_secret)) => {
+                let provider = ClientCredentialsAccessTokenProvider::new_with_token_url(
+                    credential_id,
+                    credential_secret,
+                    scopes,
+                    &token_url,
+                )?;
+                Ok(Bitski::new_with_access_token_provider(
+                    &client_id,
+                    Arc::new(provider),
+                ))
+            }
             _ => Ok(Bitski::new_unauthenticated(&client_id)),
         }
     }
@@ -112,14 +169,23 @@ impl Bitski {
         // override the rpc url if it was specified
         let network = if let Some(url) = &self.rpc_override {
             network.rpc_url = url.to_owned();
+            network.endpoint_kind = self
+                .rpc_override_kind
+                .unwrap_or_else(|| EndpointKind::from_url(url));
             network
         } else {
             network
         };
 
-        let provider =
-            BitskiWeb3Provider::new(&network, &self.client_id, self.auth_token_provider.clone());
-        Ok(provider)
+        let provider = BitskiWeb3Provider::new(
+            &network,
+            &self.client_id.expose(),
+            self.auth_token_provider.clone(),
+        );
+        match self.http_options.is_default() {
+            true => Ok(provider),
+            false => Ok(provider.with_http_options(self.http_options.clone())),
+        }
     }
 
     #[cfg(feature = "ethers")]
@@ -134,14 +200,23 @@ impl Bitski {
         // override the rpc url if it was specified
         let network = if let Some(url) = &self.rpc_override {
             network.rpc_url = url.to_owned();
+            network.endpoint_kind = self
+                .rpc_override_kind
+                .unwrap_or_else(|| EndpointKind::from_url(url));
             network
         } else {
             network
         };
 
-        let provider =
-            BitskiEthersProvider::new(&network, &self.client_id, self.auth_token_provider.clone());
-        Ok(provider)
+        let provider = BitskiEthersProvider::new(
+            &network,
+            &self.client_id.expose(),
+            self.auth_token_provider.clone(),
+        );
+        match self.http_options.is_default() {
+            true => Ok(provider),
+            false => Ok(provider.with_http_options(self.http_options.clone())),
+        }
     }
 
     pub async fn get_access_token(&self) -> Result<String, Error> {
@@ -155,4 +230,82 @@ impl Bitski {
         let provider = self.get_web3_provider(network)?;
         Ok(Web3::new(provider))
     }
+
+    /// Returns a stream of new blocks that polls the network over HTTP.
+    pub fn get_block_stream<N: TryInto<Network>>(
+        &self,
+        network: N,
+        options: PollingOptions,
+    ) -> Result<impl Stream<Item = web3::error::Result<Block<H256>>>, Error> {
+        let web3 = self.get_web3(network)?;
+        Ok(polling::block_stream(web3, options))
+    }
+
+    /// Returns a stream of logs matching `filter` that polls the network over HTTP.
+    pub fn get_log_stream<N: TryInto<Network>>(
+        &self,
+        network: N,
+        filter: Filter,
+        options: PollingOptions,
+    ) -> Result<impl Stream<Item = web3::error::Result<Log>>, Error> {
+        let web3 = self.get_web3(network)?;
+        Ok(polling::log_stream(web3, filter, options))
+    }
+
+    /// Returns a [LogFollower] for logs matching `filter`, resuming from `checkpoint` if given.
+    pub fn get_log_follower<N: TryInto<Network>>(
+        &self,
+        network: N,
+        filter: Filter,
+        options: PollingOptions,
+        checkpoint: Option<Checkpoint>,
+    ) -> Result<LogFollower<BitskiWeb3Provider>, Error> {
+        let web3 = self.get_web3(network)?;
+        let follower = match checkpoint {
+            Some(checkpoint) => LogFollower::resume(web3, filter, options, checkpoint),
+            None => LogFollower::new(web3, filter, options),
+        };
+        Ok(follower)
+    }
+
+    /// Connects to the WebSocket endpoint of a network, which supports `eth_subscribe`.
+    pub async fn get_web3_ws<N: TryInto<Network>>(
+        &self,
+        network: N,
+    ) -> Result<Web3<BitskiWs>, Error> {
+        let provider = self.connect_ws(network).await?;
+        Ok(Web3::new(provider))
+    }
+
+    /// Connects to the WebSocket endpoint of a network for use with an ethers `Provider`.
+    #[cfg(feature = "ethers")]
+    pub async fn get_ethers_ws_provider<N: TryInto<Network>>(
+        &self,
+        network: N,
+    ) -> Result<BitskiWs, Error> {
+        self.connect_ws(network).await
+    }
+
+    async fn connect_ws<N: TryInto<Network>>(&self, network: N) -> Result<BitskiWs, Error> {
+        let mut network: Network = network
+            .try_into()
+            .map_err(|_error| Error::msg("Invalid network"))?;
+
+        // override the rpc url if it was specified
+        if let Some(url) = &self.rpc_override {
+            network.rpc_url = url.to_owned();
+            network.endpoint_kind = self
+                .rpc_override_kind
+                .unwrap_or_else(|| EndpointKind::from_url(url));
+        }
+
+        BitskiWs::connect_with_options(
+            &network,
+            &self.client_id.expose(),
+            self.auth_token_provider.clone(),
+            ReconnectPolicy::default(),
+            self.http_options.clone(),
+        )
+        .await
+    }
 }